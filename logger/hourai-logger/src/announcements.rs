@@ -1,13 +1,62 @@
 use crate::Client;
 use anyhow::Result;
-use hourai::models::channel::Channel;
+use chrono::Utc;
+use hourai::models::channel::{message::embed::Embed, Channel};
 use hourai::models::gateway::payload::incoming::{BanAdd, MemberRemove};
+use hourai::models::guild::Guild;
 use hourai::models::id::{marker::*, Id};
 use hourai::models::user::User;
 use hourai::models::voice::VoiceState;
-use hourai::proto::guild_configs::*;
+use hourai::proto::{action::Action, guild_configs::*};
+use hourai::util::{placeholders::Placeholders, whois};
 use hourai_storage::Storage;
+use rand::seq::SliceRandom;
 use std::sync::Arc;
+use std::time::Duration;
+use twilight_util::builder::embed::{image_source::ImageSource, EmbedFieldBuilder};
+
+const DEFAULT_JOIN_MESSAGE: &str = "{mention} has joined the server.";
+const DEFAULT_LEAVE_MESSAGE: &str = "**{user}** has left the server.";
+const DEFAULT_BAN_MESSAGE: &str = "**{user}** has been banned.";
+const DEFAULT_VOICE_JOIN_MESSAGE: &str = "**{user}** joined **{after}**.";
+const DEFAULT_VOICE_LEAVE_MESSAGE: &str = "**{user}** left **{before}**.";
+const DEFAULT_VOICE_MOVE_MESSAGE: &str = "**{user}** moved from **{before}** to **{after}**.";
+const DEFAULT_STREAM_MESSAGE: &str = "**{user}** {status} streaming in **{channel}**.";
+
+/// The defaults used when an announcement type is configured to be sent as an embed.
+struct EmbedDefaults {
+    /// The title used if the embed config does not set one.
+    title: &'static str,
+    /// Whether the server's member count is shown as a field.
+    member_count: bool,
+}
+
+const JOIN_EMBED: EmbedDefaults = EmbedDefaults {
+    title: "Welcome to {server}!",
+    member_count: true,
+};
+const LEAVE_EMBED: EmbedDefaults = EmbedDefaults {
+    title: "{user} left {server}",
+    member_count: true,
+};
+const BAN_EMBED: EmbedDefaults = EmbedDefaults {
+    title: "{user} was banned from {server}",
+    member_count: false,
+};
+const VOICE_EMBED: EmbedDefaults = EmbedDefaults {
+    title: "Voice activity in {channel}",
+    member_count: false,
+};
+const STREAM_EMBED: EmbedDefaults = EmbedDefaults {
+    title: "{user} {status} streaming",
+    member_count: false,
+};
+
+/// How long to wait after a member stops streaming before announcing it. If they start streaming
+/// again within this window, neither the stop nor the restart is announced.
+const STREAM_DEBOUNCE: Duration = Duration::from_secs(60);
+/// The stored stop time of an announced stream that has not stopped.
+const STREAM_LIVE: u64 = 0;
 
 async fn get_config(
     storage: &Storage,
@@ -16,33 +65,188 @@ async fn get_config(
     Ok(storage.redis().guild(guild_id).configs().fetch().await?)
 }
 
+/// Picks a random custom message for the announcement type, falling back to the default message
+/// if none are configured.
+fn pick_template<'a>(config: &'a AnnouncementTypeConfig, default: &'a str) -> &'a str {
+    config
+        .get_messages()
+        .choose(&mut rand::thread_rng())
+        .map(|message| message.as_str())
+        .unwrap_or(default)
+}
+
+/// Adds the `{user}`, `{mention}`, `{server}` and `{member_count}` placeholders. The server name
+/// and member count are only looked up if one of the templates uses them.
+async fn add_user_placeholders(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    user: &User,
+    placeholders: Placeholders,
+    templates: &[&str],
+) -> Result<Placeholders> {
+    let uses = |placeholder: &str| templates.iter().any(|t| t.contains(placeholder));
+    let mut placeholders = placeholders
+        .set("user", user.name.clone())
+        .set("mention", format!("<@{}>", user.id));
+    if uses("{server}") {
+        let guild = client
+            .storage()
+            .redis()
+            .guild(guild_id)
+            .fetch_resource::<Guild>(guild_id)
+            .await?;
+        placeholders = placeholders.set(
+            "server",
+            guild
+                .map(|guild| guild.get_name().to_owned())
+                .unwrap_or_default(),
+        );
+    }
+    if uses("{member_count}") {
+        let (count,) = hourai_sql::Member::count_guild_members(guild_id, true)
+            .fetch_one(client.storage().sql())
+            .await?;
+        placeholders = placeholders.set("member_count", count.to_string());
+    }
+    Ok(placeholders)
+}
+
+/// Renders and broadcasts an announcement about a user. Provides the `{user}`, `{mention}`,
+/// `{server}` and `{member_count}` placeholders in addition to the provided ones. `embed_defaults`
+/// is only used if the announcement type is configured to be sent as an embed.
+async fn announce(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    config: &AnnouncementTypeConfig,
+    default: &str,
+    embed_defaults: &EmbedDefaults,
+    user: &User,
+    placeholders: Placeholders,
+) -> Result<()> {
+    if config.get_channel_ids().is_empty() {
+        return Ok(());
+    }
+
+    let template = pick_template(config, default);
+    if !config.has_embed() {
+        let placeholders =
+            add_user_placeholders(client, guild_id, user, placeholders, &[template]).await?;
+        broadcast(client, config, placeholders.render(template));
+        return Ok(());
+    }
+
+    let embed_config = config.get_embed();
+    let title = if embed_config.has_title() {
+        embed_config.get_title()
+    } else {
+        embed_defaults.title
+    };
+    let member_count = if embed_defaults.member_count {
+        "{member_count}"
+    } else {
+        ""
+    };
+    let templates = [template, title, member_count];
+    let placeholders =
+        add_user_placeholders(client, guild_id, user, placeholders, &templates).await?;
+    let mut embed = whois::user(user)?
+        .title(placeholders.render(title))
+        .description(placeholders.render(template));
+    if embed_defaults.member_count {
+        embed = embed.field(
+            EmbedFieldBuilder::new(
+                "Member count",
+                placeholders.get("member_count").unwrap_or_default(),
+            )
+            .inline(),
+        );
+    }
+    if embed_config.has_color() {
+        embed = embed.color(embed_config.get_color());
+    }
+    if embed_config.has_image_url() {
+        embed = embed.image(ImageSource::url(embed_config.get_image_url())?);
+    }
+    broadcast_embed(client, config, embed.build());
+    Ok(())
+}
+
 pub async fn on_member_join(client: &Client, guild: Id<GuildMarker>, user: User) -> Result<()> {
     if let Some(config) = get_config(client.storage(), guild).await? {
-        // TODO(james7132): let this be customizable.
-        let msg = format!("<@{}> has joined the server.", user.id);
-        broadcast(client, config.get_leaves(), msg);
+        let joins = config.get_joins();
+        let placeholders = Placeholders::default();
+        announce(
+            client,
+            guild,
+            joins,
+            DEFAULT_JOIN_MESSAGE,
+            &JOIN_EMBED,
+            &user,
+            placeholders,
+        )
+        .await?;
+        if joins.has_direct_message() && !user.bot {
+            send_welcome_message(client, guild, &user, joins.get_direct_message()).await?;
+        }
     }
     Ok(())
 }
 
+async fn send_welcome_message(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    user: &User,
+    template: &str,
+) -> Result<()> {
+    let placeholders =
+        add_user_placeholders(client, guild_id, user, Placeholders::default(), &[template]).await?;
+    let mut action = Action::new();
+    action.set_user_id(user.id.get());
+    action.set_guild_id(guild_id.get());
+    action.set_reason("Welcome message".to_owned());
+    action
+        .mut_direct_message()
+        .set_content(placeholders.render(template));
+    client.actions().execute_action(&action).await
+}
+
 pub async fn on_member_leave(client: &Client, evt: MemberRemove) -> Result<()> {
     if let Some(config) = get_config(client.storage(), evt.guild_id).await? {
-        // TODO(james7132): let this be customizable.
-        let msg = format!("**{}** has left the server.", evt.user.name);
-        broadcast(client, config.get_leaves(), msg);
+        let placeholders = Placeholders::default();
+        announce(
+            client,
+            evt.guild_id,
+            config.get_leaves(),
+            DEFAULT_LEAVE_MESSAGE,
+            &LEAVE_EMBED,
+            &evt.user,
+            placeholders,
+        )
+        .await?;
     }
     Ok(())
 }
 
 pub async fn on_member_ban(client: &Client, evt: BanAdd) -> Result<()> {
     if let Some(config) = get_config(client.storage(), evt.guild_id).await? {
-        // TODO(james7132): let this be customizable.
-        let msg = format!("**{}** has been banned.", evt.user.name);
-        broadcast(client, config.get_bans(), msg);
+        let placeholders = Placeholders::default();
+        announce(
+            client,
+            evt.guild_id,
+            config.get_bans(),
+            DEFAULT_BAN_MESSAGE,
+            &BAN_EMBED,
+            &evt.user,
+            placeholders,
+        )
+        .await?;
     }
     Ok(())
 }
 
+/// Announces voice channel changes. In addition to the common placeholders, `{before}` and
+/// `{after}` are the names of the channels left and joined, and `{channel}` is the name of the
+/// channel joined, or the channel left if the user disconnected.
 pub async fn on_voice_update(
     client: &Client,
     state: VoiceState,
@@ -67,31 +271,153 @@ pub async fn on_voice_update(
         return Ok(());
     }
     let user = match state.member {
-        Some(member) => member.user.name,
+        Some(member) => member.user,
         None => return Ok(()),
     };
     if let Some(config) = get_config(client.storage(), guild_id).await? {
-        // TODO(james7132): let this be customizable.
-        let msg = match (before_channel, after_channel) {
-            (Some(b), Some(a)) => {
-                format!(
-                    "**{}** moved from **{}** to **{}**.",
-                    user,
-                    b.get_name(),
-                    a.get_name()
-                )
-            }
-            (None, Some(ch)) => format!("**{}** joined **{}**.", user, ch.get_name()),
-            (Some(ch), None) => format!("**{}** left **{}**.", user, ch.get_name()),
+        let default = match (&before_channel, &after_channel) {
+            (Some(_), Some(_)) => DEFAULT_VOICE_MOVE_MESSAGE,
+            (None, Some(_)) => DEFAULT_VOICE_JOIN_MESSAGE,
+            (Some(_), None) => DEFAULT_VOICE_LEAVE_MESSAGE,
             (None, None) => return Ok(()),
         };
-
-        broadcast(&client, config.get_voice(), msg);
+        let before_name = before_channel
+            .as_ref()
+            .map(|ch| ch.get_name().to_owned())
+            .unwrap_or_default();
+        let after_name = after_channel
+            .as_ref()
+            .map(|ch| ch.get_name().to_owned())
+            .unwrap_or_default();
+        let channel = if after_channel.is_some() {
+            after_name.clone()
+        } else {
+            before_name.clone()
+        };
+        let placeholders = Placeholders::default()
+            .set("before", before_name)
+            .set("after", after_name)
+            .set("channel", channel);
+        announce(
+            client,
+            guild_id,
+            config.get_voice(),
+            default,
+            &VOICE_EMBED,
+            &user,
+            placeholders,
+        )
+        .await?;
     }
 
     Ok(())
 }
 
+/// Announces members starting and stopping streams. Stops are debounced by `STREAM_DEBOUNCE` so
+/// that flapping streams do not spam the channel. In addition to the common placeholders,
+/// `{channel}` is the name of the voice channel and `{status}` is either "started" or "stopped".
+pub async fn on_stream_update(
+    client: &Client,
+    state: &VoiceState,
+    before: Option<Id<ChannelMarker>>,
+) -> Result<()> {
+    let guild_id = match state.guild_id {
+        Some(guild) => guild,
+        None => return Ok(()),
+    };
+    let user = match state.member {
+        Some(ref member) => member.user.clone(),
+        None => return Ok(()),
+    };
+    let mut voice_states = client.storage().redis().guild(guild_id).voice_states();
+    let announced = voice_states.get_stream(user.id).await?;
+    let streaming = state.self_stream && state.channel_id.is_some();
+    match (streaming, announced) {
+        (true, None) => {
+            voice_states.set_stream(user.id, STREAM_LIVE).await?;
+            announce_stream(client, guild_id, &user, state.channel_id, "started").await?;
+        }
+        // Restarted within the debounce window. The pending stop announcement is cancelled.
+        (true, Some(stopped_at)) if stopped_at != STREAM_LIVE => {
+            voice_states.set_stream(user.id, STREAM_LIVE).await?;
+        }
+        (false, Some(STREAM_LIVE)) => {
+            let stopped_at = Utc::now().timestamp_millis() as u64;
+            voice_states.set_stream(user.id, stopped_at).await?;
+            let client = client.clone();
+            let channel_id = state.channel_id.or(before);
+            tokio::spawn(async move {
+                tokio::time::sleep(STREAM_DEBOUNCE).await;
+                let res = finish_stream(&client, guild_id, &user, channel_id, stopped_at).await;
+                if let Err(err) = res {
+                    tracing::error!("Error while announcing end of stream: {}", err);
+                }
+            });
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Announces the end of a stream if the member has not started streaming again since it stopped
+/// at `stopped_at`. Later stops schedule their own announcement.
+async fn finish_stream(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    user: &User,
+    channel_id: Option<Id<ChannelMarker>>,
+    stopped_at: u64,
+) -> Result<()> {
+    let mut voice_states = client.storage().redis().guild(guild_id).voice_states();
+    if voice_states.get_stream(user.id).await? != Some(stopped_at) {
+        return Ok(());
+    }
+    voice_states.clear_stream(user.id).await?;
+    announce_stream(client, guild_id, user, channel_id, "stopped").await
+}
+
+async fn announce_stream(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    user: &User,
+    channel_id: Option<Id<ChannelMarker>>,
+    status: &str,
+) -> Result<()> {
+    let config = match get_config(client.storage(), guild_id).await? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    let channel = match channel_id {
+        Some(id) => {
+            client
+                .storage()
+                .redis()
+                .guild(guild_id)
+                .fetch_resource::<Channel>(id)
+                .await?
+        }
+        None => None,
+    };
+    let placeholders = Placeholders::default()
+        .set(
+            "channel",
+            channel
+                .map(|ch| ch.get_name().to_owned())
+                .unwrap_or_default(),
+        )
+        .set("status", status);
+    announce(
+        client,
+        guild_id,
+        config.get_streams(),
+        DEFAULT_STREAM_MESSAGE,
+        &STREAM_EMBED,
+        user,
+        placeholders,
+    )
+    .await
+}
+
 pub fn broadcast(client: &Client, config: &AnnouncementTypeConfig, message: String) {
     async fn push(
         http: Arc<hourai::http::Client>,
@@ -117,3 +443,29 @@ pub fn broadcast(client: &Client, config: &AnnouncementTypeConfig, message: Stri
         });
     }
 }
+
+pub fn broadcast_embed(client: &Client, config: &AnnouncementTypeConfig, embed: Embed) {
+    async fn push(
+        http: Arc<hourai::http::Client>,
+        channel: Id<ChannelMarker>,
+        embed: Embed,
+    ) -> Result<()> {
+        http.create_message(channel).embeds(&[embed])?.await?;
+        Ok(())
+    }
+
+    let channel_ids = config
+        .get_channel_ids()
+        .iter()
+        .cloned()
+        .map(Id::<ChannelMarker>::new);
+    for channel_id in channel_ids {
+        let http = client.http().clone();
+        let embed = embed.clone();
+        tokio::spawn(async move {
+            if let Err(err) = push(http, channel_id, embed).await {
+                tracing::error!("Error while making announcment in {}: {}", channel_id, err);
+            }
+        });
+    }
+}
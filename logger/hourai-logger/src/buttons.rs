@@ -1,17 +1,26 @@
 use hourai::{
     interactions::proto_to_custom_id,
     models::{
-        channel::message::{ReactionType, Component, component::*},
+        channel::message::{component::*, Component, ReactionType},
         guild::Permissions,
-        id::{marker::UserMarker, Id},
+        id::{
+            marker::{InteractionMarker, UserMarker},
+            Id,
+        },
+    },
+    proto::{
+        action::*, cache::CachedRoleProto, guild_configs::RoleMenuConfig, message_components::*,
     },
-    proto::{action::*, message_components::*},
 };
 
 const VERIFY_EMOJI: &str = "✅";
 const BAN_EMOJI: &str = "☠️";
 const KICK_EMOJI: &str = "❌";
 const DELETE_EMOJI: &str = "🗑️";
+const CANCEL_EMOJI: &str = "✖️";
+
+const MAX_BUTTONS_PER_ROW: usize = 5;
+const MAX_LABEL_LENGTH: usize = 80;
 
 pub fn ban_button(user_id: Id<UserMarker>, reason: Option<&str>) -> Component {
     let mut action = Action::new();
@@ -59,3 +68,101 @@ pub fn create_action_button(
         url: None,
     })
 }
+
+/// Creates the row of buttons used to confirm or cancel a pending escalation. The escalation is
+/// identified by the ID of the interaction that created it.
+pub fn escalation_confirmation(interaction_id: Id<InteractionMarker>) -> Component {
+    let mut confirm = EscalationButton::new();
+    confirm.set_button_option(EscalationButtonOption::ESCALATION_BUTTON_CONFIRM);
+    confirm.set_interaction_id(interaction_id.get());
+
+    let mut cancel = EscalationButton::new();
+    cancel.set_button_option(EscalationButtonOption::ESCALATION_BUTTON_CANCEL);
+    cancel.set_interaction_id(interaction_id.get());
+
+    Component::ActionRow(ActionRow {
+        components: vec![
+            create_escalation_button(VERIFY_EMOJI, "Confirm", ButtonStyle::Danger, confirm),
+            create_escalation_button(CANCEL_EMOJI, "Cancel", ButtonStyle::Secondary, cancel),
+        ],
+    })
+}
+
+fn create_escalation_button(
+    emoji: &str,
+    label: &str,
+    style: ButtonStyle,
+    button: EscalationButton,
+) -> Component {
+    let mut proto = MessageComponentProto::new();
+    proto.set_escalation_button(button);
+    Component::Button(Button {
+        custom_id: Some(proto_to_custom_id(&proto).unwrap()),
+        disabled: false,
+        emoji: Some(ReactionType::Unicode {
+            name: emoji.to_owned(),
+        }),
+        label: Some(label.to_owned()),
+        style,
+        url: None,
+    })
+}
+
+/// Creates the components for a published role menu. `roles` are the menu's roles, in display
+/// order.
+pub fn role_menu(name: &str, menu: &RoleMenuConfig, roles: &[CachedRoleProto]) -> Vec<Component> {
+    let mut proto = MessageComponentProto::new();
+    proto.mut_role_menu().set_menu(name.to_owned());
+    if menu.get_select_menu() {
+        let max_values = match menu.get_max_roles() {
+            0 => roles.len(),
+            max => roles.len().min(max as usize),
+        };
+        let options = roles
+            .iter()
+            .map(|role| SelectMenuOption {
+                default: false,
+                description: None,
+                emoji: None,
+                label: role_label(role),
+                value: role.get_role_id().to_string(),
+            })
+            .collect();
+        return vec![Component::ActionRow(ActionRow {
+            components: vec![Component::SelectMenu(SelectMenu {
+                custom_id: proto_to_custom_id(&proto).unwrap(),
+                disabled: false,
+                max_values: Some(max_values as u8),
+                min_values: Some(1),
+                options,
+                placeholder: Some("Pick a role...".to_owned()),
+            })],
+        })];
+    }
+
+    roles
+        .chunks(MAX_BUTTONS_PER_ROW)
+        .map(|row| {
+            let components = row
+                .iter()
+                .map(|role| {
+                    let mut proto = proto.clone();
+                    proto.mut_role_menu().set_role_id(role.get_role_id());
+                    Component::Button(Button {
+                        custom_id: Some(proto_to_custom_id(&proto).unwrap()),
+                        disabled: false,
+                        emoji: None,
+                        label: Some(role_label(role)),
+                        style: ButtonStyle::Secondary,
+                        url: None,
+                    })
+                })
+                .collect();
+            Component::ActionRow(ActionRow { components })
+        })
+        .collect()
+}
+
+fn role_label(role: &CachedRoleProto) -> String {
+    role.get_name().chars().take(MAX_LABEL_LENGTH).collect()
+}
@@ -74,12 +74,25 @@ pub(super) async fn ban(ctx: &CommandContext, executor: &ActionExecutor) -> Resu
     } else {
         BanMember_Type::BAN
     });
+    // Softbans are used to clear out messages, so default to deleting a day of messages.
+    let delete_days = ctx.get_int("delete_days").unwrap_or(if soft { 1 } else { 0 });
+    if !(0..=7).contains(&delete_days) {
+        anyhow::bail!(InteractionError::InvalidArgument(
+            "`delete_days` must be between 0 and 7.".to_owned()
+        ));
+    }
+    base.mut_ban().set_delete_message_days(delete_days as u32);
     base.set_reason(build_reason(
         action,
         authorizer.user.as_ref().unwrap(),
         ctx.get_string("reason").ok(),
     ));
     if let Ok(duration) = ctx.get_string("duration") {
+        if soft {
+            anyhow::bail!(InteractionError::InvalidArgument(
+                "Softbans are immediately undone and cannot have a duration.".to_owned()
+            ));
+        }
         base.set_duration(parse_duration(duration)?.as_secs());
     }
 
@@ -235,11 +248,10 @@ pub(super) async fn change_role(
 
     let authorizer = ctx.member().expect("Command without user.");
     let members: Vec<_> = ctx.all_users("user").collect();
+    let role_id = ctx.get_role("role")?;
     let mut base = Action::new();
     base.set_guild_id(guild_id.get());
-    base.mut_change_role()
-        .mut_role_ids()
-        .push(ctx.get_role("role")?.get());
+    base.mut_change_role().mut_role_ids().push(role_id.get());
     base.mut_change_role().set_field_type(status);
     base.set_reason(build_reason(
         if let StatusType::APPLY = status {
@@ -250,14 +262,29 @@ pub(super) async fn change_role(
         authorizer.user.as_ref().unwrap(),
         ctx.get_string("reason").ok(),
     ));
-    if let Ok(duration) = ctx.get_string("duration") {
-        base.set_duration(parse_duration(duration)?.as_secs());
+    let duration = match ctx.get_string("duration") {
+        Ok(duration) => Some(parse_duration(duration)?),
+        Err(_) => None,
+    };
+    if let Some(duration) = duration {
+        base.set_duration(duration.as_secs());
     }
 
     let mut errors = Vec::new();
+    let mut untimed = Vec::new();
     for member_id in members.iter() {
         let mut action = base.clone();
         action.set_user_id(member_id.get());
+        // Timed changes are undone when they expire, which must not take away a role the member
+        // already had or give back one they did not have.
+        let unchanged = ctx
+            .resolve_member(*member_id)
+            .map(|member| member.roles.contains(&role_id) == (status == StatusType::APPLY))
+            .unwrap_or(false);
+        if unchanged && action.has_duration() {
+            action.clear_duration();
+            untimed.push(format!("<@{}>", member_id));
+        }
         if let Err(err) = executor.execute_action(&action).await {
             tracing::error!(
                 "Error while running /role {{add/remove}} on {}: {}",
@@ -268,15 +295,31 @@ pub(super) async fn change_role(
         }
     }
 
-    Ok(Response::direct().content(format!(
-        "{} {} users.",
+    let mut response = format!(
+        "{} {} users",
         if let StatusType::APPLY = status {
             "Added role to"
         } else {
             "Removed role from"
         },
         members.len() - errors.len()
-    )))
+    );
+    if let Some(duration) = duration {
+        response.push_str(&format!(" for {}", humantime::format_duration(duration)));
+    }
+    response.push('.');
+    if !untimed.is_empty() {
+        response.push_str(&format!(
+            " {} {} the role, so the change is not timed for them.",
+            untimed.join(", "),
+            if let StatusType::APPLY = status {
+                "already had"
+            } else {
+                "did not have"
+            }
+        ));
+    }
+    Ok(Response::direct().content(response))
 }
 
 pub(super) async fn deafen(ctx: &CommandContext, executor: &ActionExecutor) -> Result<Response> {
@@ -1,46 +1,955 @@
 use super::prelude::*;
 use anyhow::Result;
+use hourai::{
+    models::{
+        channel::Channel,
+        guild::{Permissions, Role},
+        http::attachment::Attachment,
+        RoleFlags,
+    },
+    proto::{
+        auto_config::AutoConfig,
+        guild_configs::{
+            AnnouncementConfig, AnnouncementTypeConfig, GuildConfig, LoggingConfig,
+            MemberLoggingConfig, MessageLoggingConfig, ModerationConfig, MusicConfig, RoleConfig,
+            ServerLoggingConfig, VerificationConfig,
+        },
+        util::{FilterSettings, IdFilter},
+    },
+};
+use hourai_redis::CachedGuildConfig;
+use hourai_sql::{FeedSubscription, GuildConfigRevision};
+use hourai_storage::config::{set_all_configs, set_config};
+use regex::Regex;
+use reqwest::{redirect::Policy, StatusCode};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Display,
+};
 
-pub async fn reddit_add(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+const REDDIT_FEED_TYPE: &str = "REDDIT";
+const REDDIT_USER_AGENT: &str = concat!("discord:hourai:", env!("CARGO_PKG_VERSION"));
+const MAX_IMPORT_SIZE: u64 = 1 << 20;
+const MAX_RESPONSE_LENGTH: usize = 1800;
+const MAX_HISTORY_LENGTH: i64 = 10;
+const MAX_LISTED_CHANGES: usize = 5;
+/// The largest image size, in kilobytes, that can be reuploaded from a deleted message.
+const MAX_REUPLOAD_KILOBYTES: i64 = 8 * 1024;
+
+lazy_static! {
+    // Reddit redirects requests for nonexistent subreddits to its search page, so redirects
+    // must not be followed when validating them.
+    static ref REDDIT_HTTP: reqwest::Client = reqwest::Client::builder()
+        .user_agent(REDDIT_USER_AGENT)
+        .redirect(Policy::none())
+        .build()
+        .unwrap();
+}
+
+fn require_manage_guild(ctx: &CommandContext) -> Result<()> {
+    if !ctx.has_user_permission(Permissions::MANAGE_GUILD) {
+        anyhow::bail!(InteractionError::MissingPermission("Manage Server"));
+    }
+    Ok(())
+}
+
+/// Runs a generic function with the config type for a given config section. The section names
+/// match the field names in `GuildConfig`.
+macro_rules! with_section {
+    ($section:expr, $func:ident($($arg:expr),*)) => {
+        match $section {
+            "logging" => $func::<LoggingConfig>($section, $($arg),*).await,
+            "verification" => $func::<VerificationConfig>($section, $($arg),*).await,
+            "auto" => $func::<AutoConfig>($section, $($arg),*).await,
+            "moderation" => $func::<ModerationConfig>($section, $($arg),*).await,
+            "music" => $func::<MusicConfig>($section, $($arg),*).await,
+            "announce" => $func::<AnnouncementConfig>($section, $($arg),*).await,
+            "role" => $func::<RoleConfig>($section, $($arg),*).await,
+            section => Err(InteractionError::InvalidArgument(format!(
+                "`{}` is not a valid config section.",
+                section
+            ))
+            .into()),
+        }
+    };
+}
+
+/// Applies a change to one of the guild's configs and returns the fields that were changed.
+pub(super) async fn update_config<T>(
+    ctx: &CommandContext,
+    storage: &Storage,
+    update: impl FnOnce(&mut T),
+) -> Result<Vec<String>>
+where
+    T: protobuf::Message + CachedGuildConfig + serde::Serialize,
+{
+    let guild_id = ctx.guild_id()?;
+    let mut config: T = storage.redis().guild(guild_id).configs().get().await?;
+    let before = serde_json::to_value(&config)?;
+    update(&mut config);
+    let after = serde_json::to_value(&config)?;
+    set_config(storage, guild_id, ctx.user().id, config).await?;
+
+    let mut changes = Vec::new();
+    let fields: BTreeSet<_> = [&before, &after]
+        .into_iter()
+        .filter_map(|value| value.as_object())
+        .flat_map(|fields| fields.keys())
+        .collect();
+    for field in fields {
+        diff_json(
+            field,
+            before.get(field).unwrap_or(&Value::Null),
+            after.get(field).unwrap_or(&Value::Null),
+            &mut changes,
+        );
+    }
+    Ok(changes)
+}
+
+/// Responds to a config change with a summary of the fields that were changed.
+pub(super) fn config_response(message: impl Display, changes: Vec<String>) -> Result<Response> {
+    if changes.is_empty() {
+        return Ok(Response::direct().content(format!("{}\nNo settings were changed.", message)));
+    }
+    Ok(Response::direct().content(format!(
+        "{}\n```\n{}\n```",
+        message,
+        truncate_lines(changes, "changes")
+    )))
+}
+
+fn normalize_subreddit(subreddit: &str) -> String {
+    subreddit
+        .trim()
+        .trim_start_matches("/r/")
+        .trim_start_matches("r/")
+        .to_lowercase()
+}
+
+/// Checks that a subreddit exists and is publicly visible before any posts are fetched from it.
+async fn validate_subreddit(subreddit: &str) -> Result<()> {
+    let invalid = || {
+        InteractionError::InvalidArgument(format!(
+            "`/r/{}` does not exist or is not public.",
+            subreddit
+        ))
+    };
+    let valid_name = (2..=21).contains(&subreddit.len())
+        && subreddit
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+    if !valid_name {
+        anyhow::bail!(invalid());
+    }
+
+    let response = REDDIT_HTTP
+        .get(format!("https://www.reddit.com/r/{}/about.json", subreddit))
+        .send()
+        .await?;
+    match response.status() {
+        StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => anyhow::bail!(invalid()),
+        status if status.is_redirection() => anyhow::bail!(invalid()),
+        _ => {}
+    }
+    let about: serde_json::Value = response.error_for_status()?.json().await?;
+    if about["kind"] != "t5" || about["data"]["subreddit_type"] == "private" {
+        anyhow::bail!(invalid());
+    }
+    Ok(())
+}
+
+pub async fn reddit_add(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    let subreddit = normalize_subreddit(ctx.get_string("subreddit")?);
+    validate_subreddit(&subreddit).await?;
+    let mut txn = storage.sql().begin().await?;
+    let (feed_id,) = FeedSubscription::create_feed(REDDIT_FEED_TYPE, subreddit.clone())
+        .fetch_one(&mut txn)
+        .await?;
+    FeedSubscription::subscribe(feed_id, ctx.channel_id())
+        .execute(&mut txn)
+        .await?;
+    txn.commit().await?;
+    Ok(Response::direct().content(format!(
+        "Posts from `/r/{}` will now be posted in this channel.",
+        subreddit
+    )))
+}
+
+pub async fn reddit_remove(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    let subreddit = normalize_subreddit(ctx.get_string("subreddit")?);
+    let mut txn = storage.sql().begin().await?;
+    let result =
+        FeedSubscription::unsubscribe(REDDIT_FEED_TYPE, subreddit.clone(), ctx.channel_id())
+            .execute(&mut txn)
+            .await?;
+    if result.rows_affected() == 0 {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "`/r/{}` is not being posted in this channel.",
+            subreddit
+        )));
+    }
+    FeedSubscription::delete_unused_feed(REDDIT_FEED_TYPE, subreddit.clone())
+        .execute(&mut txn)
+        .await?;
+    txn.commit().await?;
+    Ok(Response::direct().content(format!(
+        "Posts from `/r/{}` will no longer be posted in this channel.",
+        subreddit
+    )))
+}
+
+pub async fn reddit_list(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer().await?;
+    let channel_ids = storage
+        .redis()
+        .guild(ctx.guild_id()?)
+        .fetch_all_resources::<Channel>()
+        .await?
+        .into_keys();
+    let subscriptions = FeedSubscription::fetch_channels(channel_ids)
+        .fetch_all(storage.sql())
+        .await?;
+
+    let mut channels: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+    for feed in subscriptions {
+        if feed.feed_type == REDDIT_FEED_TYPE {
+            channels
+                .entry(feed.channel_id)
+                .or_default()
+                .push(format!("`/r/{}`", feed.source));
+        }
+    }
+
+    if channels.is_empty() {
+        return Ok(Response::direct().content("No subreddits are posted in this server."));
+    }
+    let lines: Vec<_> = channels
+        .into_iter()
+        .map(|(channel_id, subreddits)| format!("<#{}>: {}", channel_id, subreddits.join(", ")))
+        .collect();
+    Ok(Response::direct().content(format!(
+        "Subreddits posted in this server:\n{}",
+        lines.join("\n")
+    )))
+}
+
+pub async fn setdj(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    let role_id = ctx.get_role("role")?;
+    let changes = update_config(ctx, storage, |config: &mut MusicConfig| {
+        config.set_dj_role_id(vec![role_id.get()]);
+    })
+    .await?;
+    config_response(format!("Set the DJ role to <@&{}>.", role_id), changes)
+}
+
+pub async fn setmodlog(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    let channel_id = ctx.get_channel("channel")?;
+    let changes = update_config(ctx, storage, |config: &mut LoggingConfig| {
+        config.set_modlog_channel_id(channel_id.get());
+    })
+    .await?;
+    config_response(
+        format!("Set the modlog channel to <#{}>.", channel_id),
+        changes,
+    )
+}
+
+/// The role flags that can be changed with `/config role`, keyed by option name.
+const ROLE_FLAG_OPTIONS: [(&str, RoleFlags); 4] = [
+    ("dj", RoleFlags::DJ),
+    ("moderator", RoleFlags::MODERATOR),
+    ("restorable", RoleFlags::RESTORABLE),
+    ("self_serve", RoleFlags::SELF_SERVE),
+];
+
+pub async fn role_flags(ctx: &CommandContext, executor: &ActionExecutor) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    let storage = executor.storage();
+    let guild_id = ctx.guild_id()?;
+    let role_id = ctx.get_role("role")?;
+    let changes: Vec<_> = ROLE_FLAG_OPTIONS
+        .iter()
+        .filter_map(|&(name, flag)| ctx.get_flag(name).map(|value| (flag, value)))
+        .collect();
+    if changes.is_empty() {
+        let config: RoleConfig = storage.redis().guild(guild_id).configs().get().await?;
+        let flags = config
+            .get_settings()
+            .get(&role_id.get())
+            .map(|settings| RoleFlags::from_bits_truncate(settings.get_flags()))
+            .unwrap_or_else(RoleFlags::empty);
+        let names: Vec<_> = ROLE_FLAG_OPTIONS
+            .iter()
+            .filter(|(_, flag)| flags.contains(*flag))
+            .map(|(name, _)| format!("`{}`", name))
+            .collect();
+        let names = if names.is_empty() {
+            "none".to_owned()
+        } else {
+            names.join(", ")
+        };
+        return Ok(Response::direct().content(format!("Flags for <@&{}>: {}", role_id, names)));
+    }
+
+    // Self-serve roles are subject to the same restrictions as in `/role allow`.
+    if changes.contains(&(RoleFlags::SELF_SERVE, true)) {
+        super::roles::require_can_allow(ctx, executor, role_id).await?;
+    }
+
+    let changes = update_config(ctx, storage, |config: &mut RoleConfig| {
+        let settings = config.mut_settings().entry(role_id.get()).or_default();
+        let mut flags = RoleFlags::from_bits_truncate(settings.get_flags());
+        for (flag, value) in changes {
+            flags.set(flag, value);
+        }
+        settings.set_flags(flags.bits());
+    })
+    .await?;
+    config_response(
+        format!("Updated the settings for <@&{}>.", role_id),
+        changes,
+    )
+}
+
+/// The common fields of the per type logging configs.
+trait LogTypeConfig {
+    fn get_enabled(&self) -> bool;
+    fn set_enabled(&mut self, enabled: bool);
+    fn get_output_channel_id(&self) -> u64;
+    fn set_output_channel_id(&mut self, channel_id: u64);
+}
+
+macro_rules! impl_log_type_config {
+    ($($config:ty),*) => {
+        $(
+            impl LogTypeConfig for $config {
+                fn get_enabled(&self) -> bool {
+                    <$config>::get_enabled(self)
+                }
+
+                fn set_enabled(&mut self, enabled: bool) {
+                    <$config>::set_enabled(self, enabled)
+                }
+
+                fn get_output_channel_id(&self) -> u64 {
+                    <$config>::get_output_channel_id(self)
+                }
+
+                fn set_output_channel_id(&mut self, channel_id: u64) {
+                    <$config>::set_output_channel_id(self, channel_id)
+                }
+            }
+        )*
+    };
+}
+
+impl_log_type_config!(
+    MessageLoggingConfig,
+    MemberLoggingConfig,
+    ServerLoggingConfig
+);
+
+/// Toggles a logging type for the current channel. If the type is already logged to the current
+/// channel, it is disabled. Otherwise it is enabled and set to log to the current channel.
+async fn toggle_log<T: LogTypeConfig>(
+    ctx: &CommandContext,
+    storage: &Storage,
+    name: &str,
+    field: fn(&mut LoggingConfig) -> &mut T,
+) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    let channel_id = ctx.channel_id().get();
+    let mut enabled = false;
+    let changes = update_config(ctx, storage, |config: &mut LoggingConfig| {
+        let log_config = field(config);
+        if log_config.get_enabled() && log_config.get_output_channel_id() == channel_id {
+            log_config.set_enabled(false);
+        } else {
+            log_config.set_enabled(true);
+            log_config.set_output_channel_id(channel_id);
+            enabled = true;
+        }
+    })
+    .await?;
+    let status = if enabled { "enabled" } else { "disabled" };
+    config_response(
+        format!("Logging of {} {} in this channel.", name, status),
+        changes,
+    )
+}
+
+pub async fn log_edited(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_log(
+        ctx,
+        storage,
+        "edited messages",
+        LoggingConfig::mut_edited_messages,
+    )
+    .await
+}
+
+pub async fn log_deleted(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_log(
+        ctx,
+        storage,
+        "deleted messages",
+        LoggingConfig::mut_deleted_messages,
+    )
+    .await
+}
+
+pub async fn log_joins(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_log(
+        ctx,
+        storage,
+        "member joins and leaves",
+        LoggingConfig::mut_member_joins,
+    )
+    .await
+}
+
+pub async fn log_nicknames(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_log(
+        ctx,
+        storage,
+        "nickname changes",
+        LoggingConfig::mut_nickname_changes,
+    )
+    .await
+}
+
+pub async fn log_roles(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_log(
+        ctx,
+        storage,
+        "role changes",
+        LoggingConfig::mut_role_changes,
+    )
+    .await
+}
+
+pub async fn log_server(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_log(
+        ctx,
+        storage,
+        "server, channel and role changes",
+        LoggingConfig::mut_server_changes,
+    )
+    .await
 }
 
-pub async fn reddit_remove(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+pub async fn log_usernames(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_log(
+        ctx,
+        storage,
+        "username and avatar changes",
+        LoggingConfig::mut_username_changes,
+    )
+    .await
 }
 
-pub async fn reddit_list(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+/// Sets the largest image attached to a deleted message that is reuploaded to the deleted message
+/// log. Zero disables reuploading.
+pub async fn log_images(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    let kilobytes = ctx.get_int("kilobytes")?;
+    if !(0..=MAX_REUPLOAD_KILOBYTES).contains(&kilobytes) {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "The size must be between 0 and {} kilobytes.",
+            MAX_REUPLOAD_KILOBYTES
+        )));
+    }
+    let changes = update_config(ctx, storage, |config: &mut LoggingConfig| {
+        config
+            .mut_deleted_messages()
+            .set_max_reupload_size(kilobytes as u64 * 1024);
+    })
+    .await?;
+    let message = if kilobytes == 0 {
+        "Images from deleted messages will no longer be reuploaded.".to_owned()
+    } else {
+        format!(
+            "Images up to {} KB from deleted messages will be reuploaded when they can still be \
+             downloaded.",
+            kilobytes
+        )
+    };
+    config_response(message, changes)
 }
 
-pub async fn setdj(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+/// Toggles an announcement type for the current channel.
+async fn toggle_announcement(
+    ctx: &CommandContext,
+    storage: &Storage,
+    name: &str,
+    field: fn(&mut AnnouncementConfig) -> &mut AnnouncementTypeConfig,
+) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    let channel_id = ctx.channel_id().get();
+    let mut enabled = false;
+    let changes = update_config(ctx, storage, |config: &mut AnnouncementConfig| {
+        let channel_ids = field(config).mut_channel_ids();
+        if let Some(idx) = channel_ids.iter().position(|id| *id == channel_id) {
+            channel_ids.remove(idx);
+        } else {
+            channel_ids.push(channel_id);
+            enabled = true;
+        }
+    })
+    .await?;
+    let status = if enabled { "enabled" } else { "disabled" };
+    config_response(
+        format!("Announcements of {} {} in this channel.", name, status),
+        changes,
+    )
 }
 
-pub async fn log_edited(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+pub async fn announce_join(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_announcement(ctx, storage, "joins", AnnouncementConfig::mut_joins).await
 }
 
-pub async fn log_deleted(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+pub async fn announce_leave(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_announcement(ctx, storage, "leaves", AnnouncementConfig::mut_leaves).await
 }
 
-pub async fn setmodlog(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+pub async fn announce_ban(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_announcement(ctx, storage, "bans", AnnouncementConfig::mut_bans).await
 }
 
-pub async fn announce_join(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+pub async fn announce_voice(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_announcement(ctx, storage, "voice changes", AnnouncementConfig::mut_voice).await
 }
 
-pub async fn announce_leave(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+pub async fn announce_stream(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    toggle_announcement(ctx, storage, "streams", AnnouncementConfig::mut_streams).await
 }
 
-pub async fn announce_ban(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+pub async fn export(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    require_manage_guild(ctx)?;
+    let guild_id = ctx.guild_id()?;
+    let config = storage
+        .redis()
+        .guild(guild_id)
+        .configs()
+        .fetch_all()
+        .await?;
+    let file = serde_json::to_vec_pretty(&config)?;
+    Ok(Response::ephemeral()
+        .content("Exported the configuration for this server.")
+        .attachment(Attachment::from_bytes(
+            format!("hourai-config-{}.json", guild_id),
+            file,
+            0,
+        )))
 }
 
-pub async fn announce_voice(_ctx: &CommandContext) -> Result<Response> {
-    anyhow::bail!(InteractionError::NotImplemented);
+pub async fn import(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    let guild_id = ctx.guild_id()?;
+    let attachment = ctx.get_attachment("file")?;
+    if attachment.size > MAX_IMPORT_SIZE {
+        anyhow::bail!(InteractionError::InvalidArgument(
+            "Config files cannot be larger than 1MB.".to_owned()
+        ));
+    }
+    let file = reqwest::get(&attachment.url)
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let config: GuildConfig = serde_json::from_slice(&file).map_err(|err| {
+        InteractionError::InvalidArgument(format!("The config file is not valid: {}", err))
+    })?;
+
+    let mut guild = storage.redis().guild(guild_id);
+    let mut validator = ConfigValidator {
+        channels: guild
+            .fetch_all_resources::<Channel>()
+            .await?
+            .into_keys()
+            .map(|id| id.get())
+            .collect(),
+        roles: guild
+            .fetch_all_resources::<Role>()
+            .await?
+            .into_keys()
+            .map(|id| id.get())
+            .collect(),
+        errors: Vec::new(),
+    };
+    validator.validate(&config);
+    if !validator.errors.is_empty() {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "The config file is not valid:\n{}",
+            truncate_lines(validator.errors, "errors")
+        )));
+    }
+
+    let current = guild.configs().fetch_all().await?;
+    let changes = diff_configs(&current, &config)?;
+    if changes.is_empty() {
+        return Ok(Response::direct().content("The config file matches the current configuration."));
+    }
+    let changes = truncate_lines(changes, "changes");
+    if ctx.get_flag("dry_run").unwrap_or(false) {
+        return Ok(Response::direct().content(format!(
+            "The config file is valid. Importing it will make the following changes:\n```\n{}\n```",
+            changes
+        )));
+    }
+
+    set_all_configs(storage, guild_id, ctx.user().id, config).await?;
+    Ok(Response::direct().content(format!(
+        "Imported the config file. The following changes were made:\n```\n{}\n```",
+        changes
+    )))
+}
+
+pub async fn history(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    require_manage_guild(ctx)?;
+    with_section!(
+        ctx.get_string("section")?.as_str(),
+        section_history(ctx, storage)
+    )
+}
+
+async fn section_history<T>(
+    section: &str,
+    ctx: &CommandContext,
+    storage: &Storage,
+) -> Result<Response>
+where
+    T: protobuf::Message + CachedGuildConfig + serde::Serialize,
+{
+    // Fetch one more revision than is shown so the oldest shown revision can be diffed.
+    let revisions =
+        GuildConfigRevision::fetch_history(ctx.guild_id()?, T::SUBKEY, MAX_HISTORY_LENGTH + 1)
+            .fetch_all(storage.sql())
+            .await?;
+    if revisions.is_empty() {
+        return Ok(Response::ephemeral().content(format!(
+            "There is no recorded history for the `{}` config.",
+            section
+        )));
+    }
+
+    let configs = revisions
+        .iter()
+        .map(|revision| {
+            Ok(serde_json::to_value(T::parse_from_bytes(
+                &revision.config,
+            )?)?)
+        })
+        .collect::<Result<Vec<Value>>>()?;
+    let mut lines = Vec::new();
+    for (idx, revision) in revisions
+        .iter()
+        .enumerate()
+        .take(MAX_HISTORY_LENGTH as usize)
+    {
+        let previous = configs.get(idx + 1).unwrap_or(&Value::Null);
+        let mut changes = Vec::new();
+        diff_json(section, previous, &configs[idx], &mut changes);
+        let mut changed: Vec<_> = changes
+            .iter()
+            .take(MAX_LISTED_CHANGES)
+            .map(|change| format!("`{}`", change.split(':').next().unwrap_or_default()))
+            .collect();
+        if changes.len() > MAX_LISTED_CHANGES {
+            changed.push(format!("{} more", changes.len() - MAX_LISTED_CHANGES));
+        }
+        lines.push(format!(
+            "`v{}`: <t:{}:R> by <@{}>. Changed: {}",
+            revision.id,
+            revision.timestamp.timestamp(),
+            revision.author_id,
+            if changed.is_empty() {
+                "nothing".to_owned()
+            } else {
+                changed.join(", ")
+            }
+        ));
+    }
+
+    Ok(Response::ephemeral().content(format!(
+        "Recent changes to the `{}` config:\n{}",
+        section,
+        truncate_lines(lines, "changes")
+    )))
+}
+
+pub async fn rollback(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer().await?;
+    require_manage_guild(ctx)?;
+    with_section!(
+        ctx.get_string("section")?.as_str(),
+        section_rollback(ctx, storage)
+    )
+}
+
+async fn section_rollback<T>(
+    section: &str,
+    ctx: &CommandContext,
+    storage: &Storage,
+) -> Result<Response>
+where
+    T: protobuf::Message + CachedGuildConfig + serde::Serialize,
+{
+    let guild_id = ctx.guild_id()?;
+    let version = ctx.get_int("version")?;
+    let revision = match i32::try_from(version) {
+        Ok(id) => {
+            GuildConfigRevision::fetch(guild_id, T::SUBKEY, id)
+                .fetch_optional(storage.sql())
+                .await?
+        }
+        Err(_) => None,
+    };
+    let revision = revision.ok_or_else(|| {
+        InteractionError::InvalidArgument(format!(
+            "`v{}` is not a version of the `{}` config.",
+            version, section
+        ))
+    })?;
+
+    let config = T::parse_from_bytes(&revision.config)?;
+    let current: T = storage.redis().guild(guild_id).configs().get().await?;
+    let mut changes = Vec::new();
+    diff_json(
+        section,
+        &serde_json::to_value(&current)?,
+        &serde_json::to_value(&config)?,
+        &mut changes,
+    );
+    let new_version = set_config(storage, guild_id, ctx.user().id, config).await?;
+    Ok(Response::direct().content(format!(
+        "Rolled back the `{}` config to `v{}` as `v{}`. The following changes were made:\n```\n{}\n```",
+        section,
+        version,
+        new_version,
+        truncate_lines(changes, "changes")
+    )))
+}
+
+/// Joins lines together, dropping any that would not fit in a single message.
+fn truncate_lines(lines: Vec<String>, name: &str) -> String {
+    let total = lines.len();
+    let mut output = Vec::new();
+    let mut length = 0;
+    for line in lines {
+        length += line.len() + 1;
+        if length > MAX_RESPONSE_LENGTH {
+            break;
+        }
+        output.push(line);
+    }
+    if output.len() < total {
+        output.push(format!("...and {} more {}.", total - output.len(), name));
+    }
+    output.join("\n")
+}
+
+/// Lists every field that would change if the sections set in `new` were written over `current`.
+fn diff_configs(current: &GuildConfig, new: &GuildConfig) -> Result<Vec<String>> {
+    let current = serde_json::to_value(current)?;
+    let new = serde_json::to_value(new)?;
+    let mut changes = Vec::new();
+    if let Value::Object(sections) = &new {
+        for (section, value) in sections {
+            if !value.is_null() {
+                diff_json(section, &current[section], value, &mut changes);
+            }
+        }
+    }
+    Ok(changes)
+}
+
+fn diff_json(path: &str, old: &Value, new: &Value, changes: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            let keys: BTreeSet<_> = old_fields.keys().chain(new_fields.keys()).collect();
+            for key in keys {
+                diff_json(
+                    &format!("{}.{}", path, key),
+                    old_fields.get(key).unwrap_or(&Value::Null),
+                    new_fields.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        (Value::Null, Value::Object(_)) => {
+            diff_json(path, &Value::Object(Default::default()), new, changes)
+        }
+        (Value::Object(_), Value::Null) => {
+            diff_json(path, old, &Value::Object(Default::default()), changes)
+        }
+        _ if old != new => changes.push(format!("{}: {} -> {}", path, old, new)),
+        _ => {}
+    }
+}
+
+/// Checks that an imported config only references channels and roles that exist in the server,
+/// and that all of its filters are valid regexes.
+struct ConfigValidator {
+    channels: HashSet<u64>,
+    roles: HashSet<u64>,
+    errors: Vec<String>,
+}
+
+impl ConfigValidator {
+    fn validate(&mut self, config: &GuildConfig) {
+        let logging = config.get_logging();
+        self.channel("logging.modlog_channel_id", logging.get_modlog_channel_id());
+        self.message_logging("logging.deleted_messages", logging.get_deleted_messages());
+        self.message_logging("logging.edited_messages", logging.get_edited_messages());
+        self.channel(
+            "logging.escalations.output_channel_id",
+            logging.get_escalations().get_output_channel_id(),
+        );
+        self.member_logging("logging.member_joins", logging.get_member_joins());
+        self.member_logging("logging.nickname_changes", logging.get_nickname_changes());
+        self.member_logging("logging.role_changes", logging.get_role_changes());
+        self.member_logging("logging.username_changes", logging.get_username_changes());
+        self.channel(
+            "logging.server_changes.output_channel_id",
+            logging.get_server_changes().get_output_channel_id(),
+        );
+
+        let verification = config.get_verification();
+        self.role("verification.role_id", verification.get_role_id());
+        self.filter(
+            "verification.username.username_filter",
+            verification.get_username().get_username_filter(),
+        );
+
+        let rules = config.get_moderation().get_message_filter().get_rules();
+        for (idx, rule) in rules.iter().enumerate() {
+            let field = format!("moderation.message_filter.rules[{}].criteria", idx);
+            let criteria = rule.get_criteria();
+            for pattern in criteria.get_matches() {
+                self.regex(&format!("{}.matches", field), pattern);
+            }
+            for channel_id in criteria.get_excluded_channels() {
+                self.channel(&format!("{}.excluded_channels", field), *channel_id);
+            }
+        }
+
+        let music = config.get_music();
+        for role_id in music.get_dj_role_id() {
+            self.role("music.dj_role_id", *role_id);
+        }
+        self.channel("music.voice_channel_id", music.get_voice_channel_id());
+        for channel_id in music.get_text_channel_id() {
+            self.channel("music.text_channel_id", *channel_id);
+        }
+
+        let announce = config.get_announce();
+        let announcements = [
+            ("announce.joins", announce.get_joins()),
+            ("announce.leaves", announce.get_leaves()),
+            ("announce.bans", announce.get_bans()),
+            ("announce.streams", announce.get_streams()),
+            ("announce.voice", announce.get_voice()),
+        ];
+        for (field, announcement) in announcements {
+            for channel_id in announcement.get_channel_ids() {
+                self.channel(field, *channel_id);
+            }
+        }
+
+        let role = config.get_role();
+        for role_id in role.get_self_serve_role_ids() {
+            self.role("role.self_serve_role_ids", *role_id);
+        }
+        for role_id in role.get_settings().keys() {
+            self.role("role.settings", *role_id);
+        }
+        for (name, menu) in role.get_menus() {
+            let field = format!("role.menus.{}", name);
+            for role_id in menu.get_role_ids() {
+                self.role(&format!("{}.role_ids", field), *role_id);
+            }
+            self.channel(&format!("{}.channel_id", field), menu.get_channel_id());
+        }
+    }
+
+    fn message_logging(&mut self, field: &str, config: &MessageLoggingConfig) {
+        self.channel(
+            &format!("{}.output_channel_id", field),
+            config.get_output_channel_id(),
+        );
+        self.id_filter(
+            &format!("{}.channel_filter", field),
+            config.get_channel_filter(),
+            Self::channel,
+        );
+        self.id_filter(
+            &format!("{}.role_filter", field),
+            config.get_role_filter(),
+            Self::role,
+        );
+    }
+
+    fn member_logging(&mut self, field: &str, config: &MemberLoggingConfig) {
+        self.channel(
+            &format!("{}.output_channel_id", field),
+            config.get_output_channel_id(),
+        );
+    }
+
+    fn id_filter(&mut self, field: &str, filter: &IdFilter, check: fn(&mut Self, &str, u64)) {
+        for id in filter.get_allowlist().iter().chain(filter.get_denylist()) {
+            check(self, field, *id);
+        }
+    }
+
+    fn filter(&mut self, field: &str, filter: &FilterSettings) {
+        for pattern in filter.get_blacklist().iter().chain(filter.get_whitelist()) {
+            self.regex(field, pattern);
+        }
+    }
+
+    fn channel(&mut self, field: &str, channel_id: u64) {
+        if channel_id != 0 && !self.channels.contains(&channel_id) {
+            self.errors.push(format!(
+                "`{}`: No channel with the ID `{}` exists.",
+                field, channel_id
+            ));
+        }
+    }
+
+    fn role(&mut self, field: &str, role_id: u64) {
+        if role_id != 0 && !self.roles.contains(&role_id) {
+            self.errors.push(format!(
+                "`{}`: No role with the ID `{}` exists.",
+                field, role_id
+            ));
+        }
+    }
+
+    fn regex(&mut self, field: &str, pattern: &str) {
+        if let Err(err) = Regex::new(pattern) {
+            self.errors.push(format!(
+                "`{}`: `{}` is not a valid regex: {}",
+                field, pattern, err
+            ));
+        }
+    }
 }
@@ -1,21 +1,67 @@
-use super::prelude::*;
-use hourai_storage::escalation::EscalationManager;
+use super::{prelude::*, require_moderator};
+use crate::buttons;
+use hourai::{
+    models::{
+        id::{
+            marker::{InteractionMarker, UserMarker},
+            Id,
+        },
+        user::User,
+    },
+    proto::{escalation::PendingEscalation, message_components::EscalationButtonOption},
+};
+use hourai_storage::{
+    actions::describe_action,
+    escalation::{EscalationManager, GuildEscalationManager},
+};
+use std::time::Duration;
+
+const ESCALATION_PERMISSION: &str = "Only moderators can escalate users.";
+const ESCALATION_EXPIRED: &str =
+    "This escalation has expired or has already been confirmed or cancelled.";
+const MAX_RESPONSE_LENGTH: usize = 1800;
+const MAX_REASON_LENGTH: usize = 200;
+
+/// Shortens the reason so that it cannot fill up a response on its own.
+fn truncate_reason(reason: &str) -> String {
+    if reason.chars().count() <= MAX_REASON_LENGTH {
+        return reason.to_owned();
+    }
+    let mut reason: String = reason.chars().take(MAX_REASON_LENGTH - 3).collect();
+    reason.push_str("...");
+    reason
+}
+
+/// Joins the per user entries of a response, dropping the ones that do not fit within Discord's
+/// message length limit.
+fn format_response(header: String, entries: Vec<String>) -> String {
+    let total = entries.len();
+    let mut response = header;
+    let mut listed = 0;
+    for entry in entries {
+        if response.len() + entry.len() + 1 > MAX_RESPONSE_LENGTH {
+            break;
+        }
+        response.push('\n');
+        response.push_str(&entry);
+        listed += 1;
+    }
+    if listed < total {
+        response.push_str(&format!("\n...and {} more.", total - listed));
+    }
+    response
+}
 
 pub(super) async fn escalate(ctx: &CommandContext, actions: &ActionExecutor) -> Result<Response> {
     ctx.defer().await?;
     let guild_id = ctx.guild_id()?;
-    if !hourai_storage::is_moderator(
+    require_moderator(
         guild_id,
-        ctx.command.member.as_ref().unwrap().roles.iter().cloned(),
-        &mut actions.storage().redis().clone(),
+        ctx.command.member.as_ref(),
+        actions,
+        ESCALATION_PERMISSION,
     )
-    .await?
-    {
-        anyhow::bail!(InteractionError::MissingPermission(
-            "Only moderators can escalate users."
-        ));
-    }
-    let authorizer = ctx.user();
+    .await?;
     let reason = ctx.get_string("reason")?.as_ref();
     let amount = ctx.get_int("amount").unwrap_or(1);
     if amount <= 0 {
@@ -25,9 +71,134 @@ pub(super) async fn escalate(ctx: &CommandContext, actions: &ActionExecutor) ->
     }
     let manager = EscalationManager::new(actions.clone());
     let guild = manager.guild(guild_id).await?;
+    let users: Vec<_> = ctx.all_users("user").collect();
+    if guild.config().get_confirm_escalations() {
+        preview_escalation(&guild, ctx.command.id, &users, reason, amount).await
+    } else {
+        apply_escalation(&guild, ctx.user(), &users, reason, amount).await
+    }
+}
+
+pub(super) async fn confirm_escalation(
+    ctx: &ComponentContext,
+    actions: &ActionExecutor,
+) -> Result<()> {
+    let proto = ctx.metadata()?;
+    let button = proto.get_escalation_button();
+    let option = button.get_button_option();
+    if option == EscalationButtonOption::ESCALATION_BUTTON_UNSPECIFIED {
+        return Ok(());
+    }
+
+    ctx.defer_update().await?;
+    let guild_id = ctx.guild_id()?;
+    require_moderator(guild_id, ctx.member(), actions, ESCALATION_PERMISSION).await?;
+    // Removing the pending escalation before applying it ensures that it is only applied once,
+    // even if the button is clicked multiple times.
+    let interaction_id = Id::new_checked(button.get_interaction_id())
+        .ok_or(InteractionError::FailedPrecondition(ESCALATION_EXPIRED))?;
+    let pending = actions
+        .storage()
+        .redis()
+        .pending_escalations()
+        .take(interaction_id)
+        .await?
+        .ok_or(InteractionError::FailedPrecondition(ESCALATION_EXPIRED))?;
+    let response = if option == EscalationButtonOption::ESCALATION_BUTTON_CONFIRM {
+        let manager = EscalationManager::new(actions.clone());
+        let guild = manager.guild(guild_id).await?;
+        let users: Vec<_> = pending
+            .get_user_ids()
+            .iter()
+            .map(|id| Id::new(*id))
+            .collect();
+        apply_escalation(
+            &guild,
+            ctx.user(),
+            &users,
+            pending.get_reason(),
+            pending.get_amount(),
+        )
+        .await?
+    } else {
+        Response::direct().content("Escalation cancelled.")
+    };
+
+    ctx.reply(response.components(&[])).await
+}
+
+async fn preview_escalation(
+    guild: &GuildEscalationManager,
+    interaction_id: Id<InteractionMarker>,
+    users: &[Id<UserMarker>],
+    reason: &str,
+    amount: i64,
+) -> Result<Response> {
+    if guild.config().get_escalation_ladder().get_rung().is_empty() {
+        anyhow::bail!(InteractionError::FailedPrecondition(
+            "No escalation ladder has been configured."
+        ));
+    }
+
+    let mut entries = Vec::new();
+    for user_id in users {
+        let history = guild.fetch_history(*user_id).await?;
+        let current_level = history.current_level();
+        let target_level = current_level + amount;
+        let rung = history.get_rung(target_level).unwrap();
+        let mut lines = vec![format!(
+            "<@{}>: Level {} -> {}. **{}**",
+            user_id,
+            current_level,
+            target_level,
+            rung.get_display_name()
+        )];
+        for action in rung.get_action() {
+            lines.push(format!(" - {}", describe_action(action)));
+        }
+        if rung.has_deescalation_period() {
+            let period = Duration::from_secs(rung.get_deescalation_period());
+            lines.push(format!(
+                " - Deescalates after {}",
+                humantime::format_duration(period)
+            ));
+        }
+        entries.push(lines.join("\n"));
+    }
+
+    let mut pending = PendingEscalation::new();
+    pending
+        .mut_user_ids()
+        .extend(users.iter().map(|id| id.get()));
+    pending.set_reason(reason.to_owned());
+    pending.set_amount(amount);
+    guild
+        .storage()
+        .redis()
+        .pending_escalations()
+        .save(interaction_id, pending)
+        .await?;
+
+    let header = format!(
+        "Escalating {} users for: '{}'. Please confirm the following actions:",
+        users.len(),
+        truncate_reason(reason)
+    );
+    Ok(Response::direct()
+        .content(format_response(header, entries))
+        .components(&[buttons::escalation_confirmation(interaction_id)]))
+}
+
+async fn apply_escalation(
+    guild: &GuildEscalationManager,
+    authorizer: &User,
+    users: &[Id<UserMarker>],
+    reason: &str,
+    amount: i64,
+) -> Result<Response> {
     let mut results = Vec::new();
-    for user_id in ctx.all_users("user") {
-        let history = guild.fetch_history(user_id).await?;
+    for user_id in users {
+        let history = guild.fetch_history(*user_id).await?;
         let result = history
             .apply_delta(
                 /*authorizer=*/ authorizer,
@@ -51,29 +222,24 @@ pub(super) async fn escalate(ctx: &CommandContext, actions: &ActionExecutor) ->
         }
     }
 
-    let response = format!(
-        "Escalated {} users for: '{}'\n{}",
+    let header = format!(
+        "Escalated {} users for: '{}'",
         results.len(),
-        reason,
-        results.join("\n")
+        truncate_reason(reason)
     );
-    Ok(Response::direct().content(response))
+    Ok(Response::direct().content(format_response(header, results)))
 }
 
 pub(super) async fn deescalate(ctx: &CommandContext, actions: &ActionExecutor) -> Result<Response> {
     ctx.defer().await?;
     let guild_id = ctx.guild_id()?;
-    if !hourai_storage::is_moderator(
+    require_moderator(
         guild_id,
-        ctx.command.member.as_ref().unwrap().roles.iter().cloned(),
-        &mut actions.storage().redis().clone(),
+        ctx.command.member.as_ref(),
+        actions,
+        ESCALATION_PERMISSION,
     )
-    .await?
-    {
-        anyhow::bail!(InteractionError::MissingPermission(
-            "Only moderators can escalate users."
-        ));
-    }
+    .await?;
     let authorizer = ctx.user();
     let reason = ctx.get_string("reason")?.as_ref();
     let amount = -ctx.get_int("amount").unwrap_or(1);
@@ -110,13 +276,12 @@ pub(super) async fn deescalate(ctx: &CommandContext, actions: &ActionExecutor) -
         }
     }
 
-    let response = format!(
-        "Escalated {} users for: '{}'\n{}",
+    let header = format!(
+        "Escalated {} users for: '{}'",
         results.len(),
-        reason,
-        results.join("\n")
+        truncate_reason(reason)
     );
-    Ok(Response::direct().content(response))
+    Ok(Response::direct().content(format_response(header, results)))
 }
 
 pub(super) async fn escalate_history(
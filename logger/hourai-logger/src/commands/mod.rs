@@ -2,10 +2,19 @@ mod admin;
 mod config;
 mod escalation;
 mod prelude;
+mod roles;
+mod scheduled;
 mod standard;
 
 use anyhow::Result;
-use hourai::proto::action::StatusType;
+use hourai::{
+    models::{
+        channel::message::MessageFlags,
+        guild::PartialMember,
+        id::{marker::GuildMarker, Id},
+    },
+    proto::action::StatusType,
+};
 use prelude::*;
 
 pub async fn handle_command(ctx: CommandContext, actions: &ActionExecutor) -> Result<()> {
@@ -31,31 +40,101 @@ pub async fn handle_command(ctx: CommandContext, actions: &ActionExecutor) -> Re
             admin::change_role(&ctx, actions, StatusType::UNAPPLY).await
         }
 
+        // Self-serve role commands
+        Command::SubCommand("role", "get") => roles::get(&ctx, actions).await,
+        Command::SubCommand("role", "drop") => roles::drop(&ctx, actions).await,
+        Command::SubCommand("role", "list") => roles::list(&ctx, actions.storage()).await,
+        Command::SubCommand("role", "allow") => roles::allow(&ctx, actions).await,
+        Command::SubCommand("role", "forbid") => roles::forbid(&ctx, actions.storage()).await,
+        Command::SubCommand("role", "timed") => roles::timed(&ctx, actions).await,
+        Command::SubCommand("role", "restore-preview") => {
+            roles::restore_preview(&ctx, actions).await
+        }
+        Command::SubGroupCommand("role", "menu", "create") => {
+            roles::menu_create(&ctx, actions).await
+        }
+        Command::SubGroupCommand("role", "menu", "delete") => {
+            roles::menu_delete(&ctx, actions.storage()).await
+        }
+
         // Escalation commands
         Command::SubCommand("escalate", "up") => escalation::escalate(&ctx, actions).await,
         Command::SubCommand("escalate", "down") => escalation::deescalate(&ctx, actions).await,
         Command::SubCommand("escalate", "history") => {
             escalation::escalate_history(&ctx, actions).await
         }
+
+        // Scheduled action commands
+        Command::SubCommand("scheduled", "list") => scheduled::list(&ctx, actions).await,
+        Command::SubCommand("scheduled", "cancel") => scheduled::cancel(&ctx, actions).await,
+        Command::SubCommand("scheduled", "failed") => scheduled::failed(&ctx, actions).await,
+
         // Config commands
-        Command::SubGroupCommand("config", "reddit", "add") => config::reddit_add(&ctx).await,
-        Command::SubGroupCommand("config", "reddit", "remove") => config::reddit_remove(&ctx).await,
-        Command::SubGroupCommand("config", "reddit", "list") => config::reddit_list(&ctx).await,
+        Command::SubCommand("config", "export") => config::export(&ctx, actions.storage()).await,
+        Command::SubCommand("config", "import") => config::import(&ctx, actions.storage()).await,
+        Command::SubCommand("config", "history") => config::history(&ctx, actions.storage()).await,
+        Command::SubCommand("config", "rollback") => {
+            config::rollback(&ctx, actions.storage()).await
+        }
+        Command::SubCommand("config", "role") => config::role_flags(&ctx, actions).await,
 
-        Command::SubGroupCommand("config", "set", "dj") => config::setdj(&ctx).await,
-        Command::SubGroupCommand("config", "set", "modlog") => config::setmodlog(&ctx).await,
+        Command::SubGroupCommand("config", "reddit", "add") => {
+            config::reddit_add(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "reddit", "remove") => {
+            config::reddit_remove(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "reddit", "list") => {
+            config::reddit_list(&ctx, actions.storage()).await
+        }
+
+        Command::SubGroupCommand("config", "set", "dj") => {
+            config::setdj(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "set", "modlog") => {
+            config::setmodlog(&ctx, actions.storage()).await
+        }
 
-        Command::SubGroupCommand("config", "announce", "join") => config::announce_join(&ctx).await,
+        Command::SubGroupCommand("config", "announce", "join") => {
+            config::announce_join(&ctx, actions.storage()).await
+        }
         Command::SubGroupCommand("config", "announce", "leave") => {
-            config::announce_leave(&ctx).await
+            config::announce_leave(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "announce", "ban") => {
+            config::announce_ban(&ctx, actions.storage()).await
         }
-        Command::SubGroupCommand("config", "announce", "ban") => config::announce_ban(&ctx).await,
         Command::SubGroupCommand("config", "announce", "voice") => {
-            config::announce_voice(&ctx).await
+            config::announce_voice(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "announce", "stream") => {
+            config::announce_stream(&ctx, actions.storage()).await
         }
 
-        Command::SubGroupCommand("config", "log", "edited") => config::log_edited(&ctx).await,
-        Command::SubGroupCommand("config", "log", "deleted") => config::log_deleted(&ctx).await,
+        Command::SubGroupCommand("config", "log", "edited") => {
+            config::log_edited(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "log", "deleted") => {
+            config::log_deleted(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "log", "joins") => {
+            config::log_joins(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "log", "nicknames") => {
+            config::log_nicknames(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "log", "roles") => {
+            config::log_roles(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "log", "server") => {
+            config::log_server(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "log", "usernames") => {
+            config::log_usernames(&ctx, actions.storage()).await
+        }
+        Command::SubGroupCommand("config", "log", "images") => {
+            config::log_images(&ctx, actions.storage()).await
+        }
         _ => return Ok(()),
     };
 
@@ -78,3 +157,53 @@ pub async fn handle_command(ctx: CommandContext, actions: &ActionExecutor) -> Re
         }
     }
 }
+
+pub async fn handle_component(ctx: ComponentContext, actions: &ActionExecutor) -> Result<()> {
+    let proto = ctx.metadata()?;
+    let result = if proto.has_escalation_button() {
+        escalation::confirm_escalation(&ctx, actions).await
+    } else if proto.has_role_menu() {
+        roles::on_role_menu(&ctx, actions).await
+    } else {
+        return Ok(());
+    };
+
+    tracing::info!(
+        "Recieved message component interaction: {:?}",
+        ctx.component
+    );
+
+    // The original message has already been deferred, so errors are sent as a followup to avoid
+    // overwriting it.
+    if let Err(err) = result {
+        let command_err = err.downcast_ref::<InteractionError>();
+        let content = match command_err {
+            Some(command_err) => format!(":x: Error: {}", command_err),
+            None => ":x: Fatal Error: Internal Error has occured.".to_owned(),
+        };
+        ctx.http()
+            .interaction(ctx.application_id())
+            .create_followup(ctx.token())
+            .content(&content)?
+            .flags(MessageFlags::EPHEMERAL)
+            .await?;
+        if command_err.is_none() {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Fails with the provided message if the member is not a moderator of the guild.
+async fn require_moderator(
+    guild_id: Id<GuildMarker>,
+    member: Option<&PartialMember>,
+    actions: &ActionExecutor,
+    message: &'static str,
+) -> Result<()> {
+    let roles = member.into_iter().flat_map(|m| m.roles.iter().cloned());
+    if !hourai_storage::is_moderator(guild_id, roles, actions.storage().redis()).await? {
+        anyhow::bail!(InteractionError::MissingPermission(message));
+    }
+    Ok(())
+}
@@ -1,5 +1,5 @@
 pub use anyhow::Result;
 pub use hourai::interactions::{
-    Command, CommandContext, InteractionContext, InteractionError, Response,
+    Command, CommandContext, ComponentContext, InteractionContext, InteractionError, Response,
 };
 pub use hourai_storage::{actions::ActionExecutor, Storage};
@@ -0,0 +1,586 @@
+use super::config::{config_response, update_config};
+use super::prelude::*;
+use crate::buttons;
+use hourai::{
+    models::{
+        channel::message::MessageFlags,
+        guild::{Permissions, Role},
+        id::{
+            marker::{GuildMarker, RoleMarker},
+            Id,
+        },
+        RoleFlags,
+    },
+    proto::{
+        action::{Action, StatusType},
+        cache::CachedRoleProto,
+        guild_configs::{RoleConfig, RoleMenuConfig},
+    },
+};
+use hourai_sql::PendingAction;
+use std::collections::HashSet;
+
+/// Roles with any of these permissions cannot be made self-serve.
+pub(super) const MODERATION_PERMISSIONS: Permissions = Permissions::from_bits_truncate(
+    Permissions::ADMINISTRATOR.bits()
+        | Permissions::BAN_MEMBERS.bits()
+        | Permissions::KICK_MEMBERS.bits()
+        | Permissions::MANAGE_CHANNELS.bits()
+        | Permissions::MANAGE_GUILD.bits()
+        | Permissions::MANAGE_MESSAGES.bits()
+        | Permissions::MANAGE_ROLES.bits()
+        | Permissions::MANAGE_WEBHOOKS.bits()
+        | Permissions::MODERATE_MEMBERS.bits(),
+);
+
+/// Longer names do not fit in the custom IDs of the menu's components.
+const MAX_MENU_NAME_LENGTH: usize = 32;
+/// The most options a select menu can have, or buttons a message can have.
+const MAX_MENU_ROLES: usize = 25;
+
+pub(super) fn is_self_serve(config: &RoleConfig, role_id: Id<RoleMarker>) -> bool {
+    let flags = config
+        .get_settings()
+        .get(&role_id.get())
+        .map(|settings| RoleFlags::from_bits_truncate(settings.get_flags()))
+        .unwrap_or_else(RoleFlags::empty);
+    flags.contains(RoleFlags::SELF_SERVE)
+        || config.get_self_serve_role_ids().contains(&role_id.get())
+}
+
+async fn fetch_role(
+    storage: &Storage,
+    guild_id: Id<GuildMarker>,
+    role_id: Id<RoleMarker>,
+) -> Result<CachedRoleProto> {
+    let role = storage
+        .redis()
+        .guild(guild_id)
+        .fetch_resource::<Role>(role_id)
+        .await?;
+    role.ok_or_else(|| {
+        anyhow::anyhow!(InteractionError::InvalidArgument(format!(
+            "<@&{}> is not a valid role.",
+            role_id
+        )))
+    })
+}
+
+/// Fails if the bot's highest role is not above the role, as the bot cannot assign it.
+async fn require_bot_above(
+    executor: &ActionExecutor,
+    guild_id: Id<GuildMarker>,
+    role: &CachedRoleProto,
+) -> Result<()> {
+    let bot_id = executor.current_user().id;
+    let bot = hourai_sql::Member::fetch(guild_id, bot_id)
+        .fetch_one(executor.storage().sql())
+        .await?;
+    let bot_roles: Vec<_> = bot.role_ids().collect();
+    let bot_roles = executor
+        .storage()
+        .redis()
+        .guild(guild_id)
+        .role_set(&bot_roles)
+        .await?;
+    if bot_roles.highest().map(|highest| role < highest) != Some(true) {
+        anyhow::bail!(InteractionError::FailedPrecondition(
+            "The bot's highest role must be above the role to change it."
+        ));
+    }
+    Ok(())
+}
+
+async fn change_own_role(
+    ctx: &CommandContext,
+    executor: &ActionExecutor,
+    status: StatusType,
+) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx.guild_id()?;
+    let role_id = ctx.get_role("role")?;
+    let config: RoleConfig = executor
+        .storage()
+        .redis()
+        .guild(guild_id)
+        .configs()
+        .get()
+        .await?;
+    if !is_self_serve(&config, role_id) {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "<@&{}> is not a self-serve role. Use `/role list` to see the available roles.",
+            role_id
+        )));
+    }
+    let role = fetch_role(executor.storage(), guild_id, role_id).await?;
+    // The role's permissions may have changed since it was made self-serve.
+    require_no_moderation_permissions(&role)?;
+    require_bot_above(executor, guild_id, &role).await?;
+
+    let mut action = Action::new();
+    action.set_user_id(ctx.user().id.get());
+    action.set_guild_id(guild_id.get());
+    action.set_reason("Self-serve role".to_owned());
+    action.mut_change_role().set_field_type(status);
+    action.mut_change_role().mut_role_ids().push(role_id.get());
+    executor.execute_action(&action).await?;
+
+    let verb = if let StatusType::APPLY = status {
+        "Gave you"
+    } else {
+        "Removed"
+    };
+    Ok(Response::ephemeral().content(format!("{} <@&{}>.", verb, role_id)))
+}
+
+pub(super) async fn get(ctx: &CommandContext, executor: &ActionExecutor) -> Result<Response> {
+    change_own_role(ctx, executor, StatusType::APPLY).await
+}
+
+pub(super) async fn drop(ctx: &CommandContext, executor: &ActionExecutor) -> Result<Response> {
+    change_own_role(ctx, executor, StatusType::UNAPPLY).await
+}
+
+pub(super) async fn list(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx.guild_id()?;
+    let mut guild = storage.redis().guild(guild_id);
+    let config: RoleConfig = guild.configs().get().await?;
+    let mut roles: Vec<_> = guild
+        .fetch_all_resources::<Role>()
+        .await?
+        .into_iter()
+        .filter(|(role_id, _)| is_self_serve(&config, *role_id))
+        .map(|(_, role)| role)
+        .collect();
+    if roles.is_empty() {
+        return Ok(Response::ephemeral().content("This server has no self-serve roles."));
+    }
+
+    roles.sort_by(|a, b| b.cmp(a));
+    let roles: Vec<_> = roles
+        .iter()
+        .map(|role| format!("<@&{}>", role.get_role_id()))
+        .collect();
+    Ok(Response::ephemeral().content(format!(
+        "Self-serve roles: {}\nUse `/role get` to get one of them.",
+        roles.join(", ")
+    )))
+}
+
+/// Fails if the role cannot be managed by the command's user: only roles below the user's highest
+/// role can be managed.
+async fn require_authorizer_above(
+    ctx: &CommandContext,
+    storage: &Storage,
+    role: &CachedRoleProto,
+) -> Result<()> {
+    let guild_id = ctx.guild_id()?;
+    let authorizer = ctx.member().expect("Command without user.");
+    let authorizer_roles = storage
+        .redis()
+        .guild(guild_id)
+        .role_set(&authorizer.roles)
+        .await?;
+    if authorizer_roles.highest().map(|highest| role < highest) != Some(true) {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "<@&{}> is not below your highest role.",
+            role.get_role_id()
+        )));
+    }
+    Ok(())
+}
+
+/// Fails if the role has moderation permissions, as members must not be able to give them to
+/// themselves.
+fn require_no_moderation_permissions(role: &CachedRoleProto) -> Result<()> {
+    let permissions = Permissions::from_bits_truncate(role.get_permissions());
+    if permissions.intersects(MODERATION_PERMISSIONS) {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "<@&{}> has moderation permissions and cannot be a self-serve role.",
+            role.get_role_id()
+        )));
+    }
+    Ok(())
+}
+
+/// Fails if the command's user cannot make the role self-serve. Shared by `/role allow` and
+/// `/config role`.
+pub(super) async fn require_can_allow(
+    ctx: &CommandContext,
+    executor: &ActionExecutor,
+    role_id: Id<RoleMarker>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id()?;
+    if !ctx.has_user_permission(Permissions::MANAGE_ROLES) {
+        anyhow::bail!(InteractionError::MissingPermission("Manage Roles"));
+    }
+    if role_id.get() == guild_id.get() {
+        anyhow::bail!(InteractionError::InvalidArgument(
+            "@everyone cannot be a self-serve role.".to_owned()
+        ));
+    }
+    let storage = executor.storage();
+    let role = fetch_role(storage, guild_id, role_id).await?;
+    require_authorizer_above(ctx, storage, &role).await?;
+    require_bot_above(executor, guild_id, &role).await?;
+    require_no_moderation_permissions(&role)
+}
+
+pub(super) async fn allow(ctx: &CommandContext, executor: &ActionExecutor) -> Result<Response> {
+    ctx.defer().await?;
+    let role_id = ctx.get_role("role")?;
+    require_can_allow(ctx, executor, role_id).await?;
+
+    let storage = executor.storage();
+    let changes = update_config(ctx, storage, |config: &mut RoleConfig| {
+        let settings = config.mut_settings().entry(role_id.get()).or_default();
+        let flags = RoleFlags::from_bits_truncate(settings.get_flags()) | RoleFlags::SELF_SERVE;
+        settings.set_flags(flags.bits());
+    })
+    .await?;
+    config_response(
+        format!("<@&{}> is now a self-serve role.", role_id),
+        changes,
+    )
+}
+
+pub(super) async fn forbid(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer().await?;
+    let guild_id = ctx.guild_id()?;
+    if !ctx.has_user_permission(Permissions::MANAGE_ROLES) {
+        anyhow::bail!(InteractionError::MissingPermission("Manage Roles"));
+    }
+    let role_id = ctx.get_role("role")?;
+    let role = fetch_role(storage, guild_id, role_id).await?;
+    require_authorizer_above(ctx, storage, &role).await?;
+
+    let changes = update_config(ctx, storage, |config: &mut RoleConfig| {
+        if let Some(settings) = config.mut_settings().get_mut(&role_id.get()) {
+            let flags = RoleFlags::from_bits_truncate(settings.get_flags()) - RoleFlags::SELF_SERVE;
+            settings.set_flags(flags.bits());
+        }
+        config
+            .mut_self_serve_role_ids()
+            .retain(|id| *id != role_id.get());
+    })
+    .await?;
+    config_response(
+        format!("<@&{}> is no longer a self-serve role.", role_id),
+        changes,
+    )
+}
+
+/// Lists a user's timed roles: role changes scheduled to be undone.
+pub(super) async fn timed(ctx: &CommandContext, executor: &ActionExecutor) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx.guild_id()?;
+    let user_id = ctx.get_user("user").unwrap_or_else(|_| ctx.user().id);
+    if user_id != ctx.user().id && !ctx.has_user_permission(Permissions::MANAGE_ROLES) {
+        anyhow::bail!(InteractionError::MissingPermission("Manage Roles"));
+    }
+
+    let pending = PendingAction::fetch_user(guild_id, user_id)
+        .fetch_all(executor.storage().sql())
+        .await?;
+    let lines: Vec<_> = pending
+        .iter()
+        .filter(|pending| pending.action().has_change_role())
+        .flat_map(|pending| {
+            let change_role = pending.action().get_change_role();
+            let verb = match change_role.get_field_type() {
+                StatusType::APPLY => "given back",
+                StatusType::UNAPPLY => "removed",
+                StatusType::TOGGLE => "toggled",
+            };
+            change_role.get_role_ids().iter().map(move |role_id| {
+                format!(
+                    "<@&{}>: {} <t:{}:R>",
+                    role_id,
+                    verb,
+                    pending.timestamp().timestamp()
+                )
+            })
+        })
+        .collect();
+    if lines.is_empty() {
+        return Ok(Response::ephemeral().content(format!("<@{}> has no timed roles.", user_id)));
+    }
+
+    Ok(Response::ephemeral().content(format!(
+        "Timed roles for <@{}>:\n{}",
+        user_id,
+        lines.join("\n")
+    )))
+}
+
+/// Shows the roles that would be restored to a user if they rejoined the server.
+pub(super) async fn restore_preview(
+    ctx: &CommandContext,
+    executor: &ActionExecutor,
+) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx.guild_id()?;
+    if !ctx.has_user_permission(Permissions::MANAGE_ROLES) {
+        anyhow::bail!(InteractionError::MissingPermission("Manage Roles"));
+    }
+    let user_id = ctx.get_user("user")?;
+    let storage = executor.storage();
+    let bot_id = executor.current_user().id;
+    let bot_roles = crate::roles::get_bot_roles(storage, guild_id, bot_id).await?;
+    let roles = crate::roles::find_restorable_roles(storage, guild_id, &bot_roles, user_id).await?;
+
+    let mut response = if roles.is_empty() {
+        format!("No roles would be restored to <@{}>.", user_id)
+    } else {
+        format!(
+            "If <@{}> rejoins, these roles would be restored: {}",
+            user_id,
+            describe_roles(&roles)
+        )
+    };
+    let perms = storage
+        .redis()
+        .guild(guild_id)
+        .guild_permissions(bot_id, bot_roles.into_iter())
+        .await?;
+    if !perms.contains(Permissions::MANAGE_ROLES) {
+        response.push_str("\nRoles will not be restored: the bot does not have Manage Roles.");
+    }
+    Ok(Response::ephemeral().content(response))
+}
+
+pub(super) async fn menu_create(
+    ctx: &CommandContext,
+    executor: &ActionExecutor,
+) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx.guild_id()?;
+    if !ctx.has_user_permission(Permissions::MANAGE_ROLES) {
+        anyhow::bail!(InteractionError::MissingPermission("Manage Roles"));
+    }
+    let storage = executor.storage();
+    let name = ctx.get_string("name")?.trim().to_owned();
+    if name.is_empty() || name.len() > MAX_MENU_NAME_LENGTH {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "Role menu names must be between 1 and {} characters long.",
+            MAX_MENU_NAME_LENGTH
+        )));
+    }
+
+    let mut role_ids = Vec::new();
+    for role_id in ctx.all_roles("role_") {
+        if !role_ids.contains(&role_id) {
+            role_ids.push(role_id);
+        }
+    }
+    if role_ids.is_empty() || role_ids.len() > MAX_MENU_ROLES {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "Role menus must have between 1 and {} roles.",
+            MAX_MENU_ROLES
+        )));
+    }
+    let max_roles = ctx.get_int("max_roles").unwrap_or(0);
+    if !(0..=role_ids.len() as i64).contains(&max_roles) {
+        anyhow::bail!(InteractionError::InvalidArgument(
+            "`max_roles` must be between 0 and the number of roles in the menu.".to_owned()
+        ));
+    }
+
+    let mut guild = storage.redis().guild(guild_id);
+    let config: RoleConfig = guild.configs().get().await?;
+    if config.get_menus().contains_key(&name) {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "A role menu named `{}` already exists. Delete it first to replace it.",
+            name
+        )));
+    }
+    if let Some(role_id) = role_ids.iter().find(|id| !is_self_serve(&config, **id)) {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "<@&{}> is not a self-serve role. Use `/role allow` to make it one first.",
+            role_id
+        )));
+    }
+    let roles = guild.fetch_resources::<Role>(&role_ids).await?;
+    if roles.len() != role_ids.len() {
+        anyhow::bail!(InteractionError::InvalidArgument(
+            "One or more of the roles could not be found.".to_owned()
+        ));
+    }
+
+    let mut menu = RoleMenuConfig::new();
+    menu.set_select_menu(ctx.get_flag("select").unwrap_or(false));
+    menu.set_max_roles(max_roles as u32);
+    menu.mut_role_ids()
+        .extend(role_ids.iter().map(|id| id.get()));
+
+    let components = buttons::role_menu(&name, &menu, &roles);
+    let mut request = ctx
+        .http()
+        .create_message(ctx.channel_id())
+        .components(&components)?;
+    if let Ok(content) = ctx.get_string("message") {
+        request = request.content(content)?;
+    }
+    let message = request.await?.model().await?;
+    menu.set_channel_id(message.channel_id.get());
+    menu.set_message_id(message.id.get());
+
+    update_config(ctx, storage, |config: &mut RoleConfig| {
+        config.mut_menus().insert(name.clone(), menu);
+    })
+    .await?;
+    Ok(Response::ephemeral().content(format!("Created role menu `{}`.", name)))
+}
+
+pub(super) async fn menu_delete(ctx: &CommandContext, storage: &Storage) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    if !ctx.has_user_permission(Permissions::MANAGE_ROLES) {
+        anyhow::bail!(InteractionError::MissingPermission("Manage Roles"));
+    }
+    let name = ctx.get_string("name")?.trim().to_owned();
+    let mut menu = None;
+    update_config(ctx, storage, |config: &mut RoleConfig| {
+        menu = config.mut_menus().remove(&name);
+    })
+    .await?;
+    let menu = menu.ok_or_else(|| {
+        anyhow::anyhow!(InteractionError::InvalidArgument(format!(
+            "There is no role menu named `{}`.",
+            name
+        )))
+    })?;
+
+    // The menu's message may have already been deleted by hand.
+    let channel_id = Id::new_checked(menu.get_channel_id());
+    let message_id = Id::new_checked(menu.get_message_id());
+    if let (Some(channel_id), Some(message_id)) = (channel_id, message_id) {
+        if let Err(err) = ctx.http().delete_message(channel_id, message_id).await {
+            tracing::debug!("Failed to delete role menu message: {}", err);
+        }
+    }
+    Ok(Response::ephemeral().content(format!("Deleted role menu `{}`.", name)))
+}
+
+/// Toggles the roles picked from a role menu. If the menu only allows one role, picking a role
+/// replaces the user's other roles from the menu.
+pub(super) async fn on_role_menu(ctx: &ComponentContext, executor: &ActionExecutor) -> Result<()> {
+    ctx.defer_update().await?;
+    let guild_id = ctx.guild_id()?;
+    let proto = ctx.metadata()?;
+    let component = proto.get_role_menu();
+    let config: RoleConfig = executor
+        .storage()
+        .redis()
+        .guild(guild_id)
+        .configs()
+        .get()
+        .await?;
+    let menu = config.get_menus().get(component.get_menu()).ok_or(
+        InteractionError::FailedPrecondition("This role menu no longer exists."),
+    )?;
+
+    let picked: Vec<Id<RoleMarker>> = if component.has_role_id() {
+        Id::new_checked(component.get_role_id())
+            .into_iter()
+            .collect()
+    } else {
+        ctx.values()
+            .iter()
+            .filter_map(|value| value.parse().ok())
+            .filter_map(Id::new_checked)
+            .collect()
+    };
+    let menu_roles: HashSet<_> = menu.get_role_ids().iter().cloned().map(Id::new).collect();
+    let picked: Vec<_> = picked
+        .into_iter()
+        .filter(|id| menu_roles.contains(id) && is_self_serve(&config, *id))
+        .collect();
+    if picked.is_empty() {
+        anyhow::bail!(InteractionError::FailedPrecondition(
+            "That role is no longer available."
+        ));
+    }
+    for role_id in picked.iter() {
+        let role = fetch_role(executor.storage(), guild_id, *role_id).await?;
+        require_no_moderation_permissions(&role)?;
+        require_bot_above(executor, guild_id, &role).await?;
+    }
+
+    let held: HashSet<_> = ctx
+        .member()
+        .into_iter()
+        .flat_map(|member| member.roles.iter().cloned())
+        .filter(|id| menu_roles.contains(id))
+        .collect();
+    let mut result = held.clone();
+    for role_id in picked.iter() {
+        if !result.remove(role_id) {
+            result.insert(*role_id);
+        }
+    }
+    let max_roles = menu.get_max_roles() as usize;
+    if max_roles > 0 && result.len() > max_roles {
+        if max_roles == 1 && picked.len() == 1 {
+            result = picked.iter().cloned().collect();
+        } else {
+            anyhow::bail!(InteractionError::InvalidArgument(format!(
+                "You can only have {} roles from this menu.",
+                max_roles
+            )));
+        }
+    }
+    let replaced: Vec<_> = held
+        .difference(&result)
+        .filter(|id| !picked.contains(id))
+        .cloned()
+        .collect();
+
+    let mut toggle = Action::new();
+    toggle.set_user_id(ctx.user().id.get());
+    toggle.set_guild_id(guild_id.get());
+    toggle.set_reason("Role menu".to_owned());
+    toggle.mut_change_role().set_field_type(StatusType::TOGGLE);
+    toggle
+        .mut_change_role()
+        .mut_role_ids()
+        .extend(picked.iter().map(|id| id.get()));
+    executor.execute_action(&toggle).await?;
+    if !replaced.is_empty() {
+        let mut unapply = toggle.clone();
+        unapply
+            .mut_change_role()
+            .set_field_type(StatusType::UNAPPLY);
+        unapply
+            .mut_change_role()
+            .set_role_ids(replaced.iter().map(|id| id.get()).collect());
+        executor.execute_action(&unapply).await?;
+    }
+
+    let mut added: Vec<_> = result.difference(&held).cloned().collect();
+    let mut removed: Vec<_> = held.difference(&result).cloned().collect();
+    added.sort();
+    removed.sort();
+    let mut lines = Vec::new();
+    if !added.is_empty() {
+        lines.push(format!("Added: {}", describe_roles(&added)));
+    }
+    if !removed.is_empty() {
+        lines.push(format!("Removed: {}", describe_roles(&removed)));
+    }
+    ctx.http()
+        .interaction(ctx.application_id())
+        .create_followup(ctx.token())
+        .content(&lines.join("\n"))?
+        .flags(MessageFlags::EPHEMERAL)
+        .await?;
+    Ok(())
+}
+
+fn describe_roles(role_ids: &[Id<RoleMarker>]) -> String {
+    role_ids
+        .iter()
+        .map(|id| format!("<@&{}>", id))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
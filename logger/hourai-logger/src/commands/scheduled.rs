@@ -0,0 +1,140 @@
+use super::{prelude::*, require_moderator};
+use hourai_sql::{FailedAction, PendingAction};
+use hourai_storage::actions::describe_action;
+
+const SCHEDULED_PERMISSION: &str = "Only moderators can manage scheduled actions.";
+const MAX_LISTED_ACTIONS: usize = 20;
+const MAX_RESPONSE_LENGTH: usize = 1800;
+const MAX_REASON_LENGTH: usize = 100;
+
+/// Joins up to MAX_LISTED_ACTIONS lines together, dropping any that would not fit in a single
+/// message.
+fn format_listing(lines: Vec<String>, empty: &str) -> String {
+    if lines.is_empty() {
+        return empty.to_owned();
+    }
+    let total = lines.len();
+    let mut response = Vec::new();
+    let mut length = 0;
+    for line in lines.into_iter().take(MAX_LISTED_ACTIONS) {
+        length += line.len() + 1;
+        if length > MAX_RESPONSE_LENGTH {
+            break;
+        }
+        response.push(line);
+    }
+    if response.len() < total {
+        response.push(format!("...and {} more.", total - response.len()));
+    }
+    response.join("\n")
+}
+
+/// Shortens user provided text so that a single entry cannot fill up a listing.
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_REASON_LENGTH {
+        return text.to_owned();
+    }
+    let mut text: String = text.chars().take(MAX_REASON_LENGTH - 3).collect();
+    text.push_str("...");
+    text
+}
+
+pub(super) async fn list(ctx: &CommandContext, actions: &ActionExecutor) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx.guild_id()?;
+    require_moderator(
+        guild_id,
+        ctx.command.member.as_ref(),
+        actions,
+        SCHEDULED_PERMISSION,
+    )
+    .await?;
+
+    let query = match ctx.get_user("user") {
+        Ok(user_id) => PendingAction::fetch_user(guild_id, user_id),
+        Err(_) => PendingAction::fetch_guild(guild_id),
+    };
+    let pending = query.fetch_all(actions.storage().sql()).await?;
+    let lines = pending
+        .iter()
+        .map(|pending| {
+            let user = match pending.user_id() {
+                Some(user_id) => format!("<@{}>", user_id),
+                None => "*no user*".to_owned(),
+            };
+            format!(
+                "`{}`: <t:{}:R> {}: {}. Reason: {}",
+                pending.id(),
+                pending.timestamp().timestamp(),
+                user,
+                describe_action(pending.action()),
+                truncate(pending.action().get_reason())
+            )
+        })
+        .collect();
+
+    Ok(Response::ephemeral().content(format_listing(lines, "There are no scheduled actions.")))
+}
+
+pub(super) async fn cancel(ctx: &CommandContext, actions: &ActionExecutor) -> Result<Response> {
+    ctx.defer().await?;
+    let guild_id = ctx.guild_id()?;
+    require_moderator(
+        guild_id,
+        ctx.command.member.as_ref(),
+        actions,
+        SCHEDULED_PERMISSION,
+    )
+    .await?;
+
+    let id = ctx.get_int("id")?;
+    let id = i32::try_from(id).map_err(|_| {
+        InteractionError::InvalidArgument(format!("`{}` is not a valid scheduled action ID.", id))
+    })?;
+    let result = PendingAction::cancel(guild_id, id)
+        .execute(actions.storage().sql())
+        .await?;
+    if result.rows_affected() == 0 {
+        anyhow::bail!(InteractionError::InvalidArgument(format!(
+            "No scheduled action with the ID `{}` exists.",
+            id
+        )));
+    }
+
+    Ok(Response::direct().content(format!("Cancelled scheduled action `{}`.", id)))
+}
+
+pub(super) async fn failed(ctx: &CommandContext, actions: &ActionExecutor) -> Result<Response> {
+    ctx.defer_ephemeral().await?;
+    let guild_id = ctx.guild_id()?;
+    require_moderator(
+        guild_id,
+        ctx.command.member.as_ref(),
+        actions,
+        SCHEDULED_PERMISSION,
+    )
+    .await?;
+
+    let failed = FailedAction::fetch_guild(guild_id, MAX_LISTED_ACTIONS as i64)
+        .fetch_all(actions.storage().sql())
+        .await?;
+    let lines = failed
+        .iter()
+        .map(|failed| {
+            format!(
+                "`{}`: <t:{}:R> <@{}>: {}. Failed after {} attempts: {}",
+                failed.id,
+                failed.timestamp.timestamp(),
+                failed.user_id,
+                describe_action(failed.action()),
+                failed.attempts,
+                truncate(&failed.error)
+            )
+        })
+        .collect();
+
+    Ok(Response::ephemeral().content(format_listing(
+        lines,
+        "There are no recently failed scheduled actions.",
+    )))
+}
@@ -6,10 +6,12 @@ mod buttons;
 mod commands;
 mod listings;
 mod member_chunker;
+mod member_logging;
 mod message_filter;
 mod message_logging;
 mod pending_events;
 mod roles;
+mod server_logging;
 mod utils;
 
 use anyhow::Result;
@@ -28,6 +30,7 @@ use hourai::{
         http::interaction::*,
         id::{marker::*, Id},
         user::User,
+        UserLike,
     },
 };
 use hourai_redis::*;
@@ -148,6 +151,10 @@ async fn main() {
     // Setup background tasks
     tokio::spawn(client.clone().log_bans());
     tokio::spawn(flush_online(cache.clone(), storage.redis().clone()));
+    tokio::spawn(utils::log_error(
+        "backfilling pending action owners",
+        pending_events::backfill_pending_action_owners(actions.clone()),
+    ));
     tokio::spawn(pending_events::run_pending_actions(actions.clone()));
     tokio::spawn(pending_events::run_pending_deescalations(actions.clone()));
 
@@ -251,6 +258,11 @@ impl Client {
         self.0.actions.http()
     }
 
+    #[inline(always)]
+    pub fn actions(&self) -> &ActionExecutor {
+        &self.0.actions
+    }
+
     pub async fn fetch_guild_permissions(
         &self,
         guild_id: Id<GuildMarker>,
@@ -428,7 +440,10 @@ impl Client {
             self.log_members(&members).await?;
             res?;
         }
-        announcements::on_member_join(&self, member.guild_id, member.user).await?;
+        let log = member_logging::on_member_join(&self, &member).await;
+        let res = announcements::on_member_join(&self, member.guild_id, member.user).await;
+        log?;
+        res?;
         Ok(())
     }
 
@@ -448,14 +463,51 @@ impl Client {
             return Ok(());
         }
 
+        let before = hourai_sql::Member::fetch(evt.guild_id, evt.user.id)
+            .fetch_optional(self.storage().sql())
+            .await?;
+        let latest = Username::fetch_latest(evt.user.id)
+            .fetch_optional(self.storage().sql())
+            .await?;
+        let username = Username::new(&evt.user);
+        let changed = latest.as_ref().map_or(true, |latest| {
+            latest.name != username.name || latest.discriminator != username.discriminator
+        });
+
         let mut txn = self.storage().sql().begin().await?;
         txn.execute(hourai_sql::Member::from(&evt).insert()).await?;
-        txn.execute(Username::new(&evt.user).insert()).await?;
+        if changed {
+            txn.execute(username.insert()).await?;
+        }
         txn.commit().await?;
+
+        if let Some(before) = before {
+            member_logging::on_member_update(&self, &before, &evt).await?;
+        }
+
+        // An update is received for every server the user is in. Only the one that swaps out the
+        // old username logs the change. If no username was cached, fall back to the database.
+        let current = evt.user.display_name();
+        let previous = self
+            .storage()
+            .redis()
+            .usernames()
+            .swap(evt.user.id, &current)
+            .await?;
+        let previous = match previous {
+            Some(previous) => Some(previous).filter(|previous| *previous != current),
+            None => latest
+                .filter(|_| changed)
+                .map(|latest| member_logging::describe_username(&latest)),
+        };
+        if let Some(previous) = previous {
+            member_logging::on_username_change(&self, &previous, &evt.user).await?;
+        }
         Ok(())
     }
 
     async fn on_member_remove(&self, evt: MemberRemove) -> Result<()> {
+        let log = member_logging::on_member_leave(&self, &evt).await;
         let (res1, res2, res3) = futures::join!(
             self.storage().execute(hourai_sql::Member::set_present(
                 evt.guild_id,
@@ -468,10 +520,12 @@ impl Client {
         res1?;
         res2?;
         res3?;
+        log?;
         Ok(())
     }
 
     async fn on_channel_create(&self, evt: ChannelCreate) -> Result<()> {
+        let res = server_logging::on_channel_create(self, &evt.0).await;
         if let Some(guild_id) = evt.0.guild_id {
             self.storage()
                 .redis()
@@ -479,10 +533,11 @@ impl Client {
                 .save_resource(evt.0.id, &evt.0)
                 .await?;
         }
-        Ok(())
+        res
     }
 
     async fn on_channel_update(&self, evt: ChannelUpdate) -> Result<()> {
+        let res = server_logging::on_channel_update(self, &evt.0).await;
         if let Some(guild_id) = evt.0.guild_id {
             self.storage()
                 .redis()
@@ -490,10 +545,11 @@ impl Client {
                 .save_resource(evt.0.id, &evt.0)
                 .await?;
         }
-        Ok(())
+        res
     }
 
     async fn on_channel_delete(self, evt: ChannelDelete) -> Result<()> {
+        let res = server_logging::on_channel_delete(&self, &evt.0).await;
         if let Some(guild_id) = evt.0.guild_id {
             self.storage()
                 .redis()
@@ -501,7 +557,7 @@ impl Client {
                 .delete_resource::<Channel>(evt.0.id)
                 .await?;
         }
-        Ok(())
+        res
     }
 
     async fn on_thread_create(&mut self, evt: ThreadCreate) -> Result<()> {
@@ -559,7 +615,7 @@ impl Client {
                     before.clone(),
                     msg,
                 ));
-                messages.cache(before).await?;
+                messages.save(evt.channel_id, evt.id, before).await?;
             }
         }
 
@@ -588,6 +644,13 @@ impl Client {
                 );
                 commands::handle_command(ctx, &self.0.actions).await?;
             }
+            InteractionType::MessageComponent => {
+                let ctx = hourai::interactions::ComponentContext::new(
+                    self.http().clone(),
+                    evt,
+                );
+                commands::handle_component(ctx, &self.0.actions).await?;
+            }
             interaction => {
                 warn!("Unknown incoming interaction: {:?}", interaction);
                 return Ok(());
@@ -607,16 +670,13 @@ impl Client {
     }
 
     async fn on_message_bulk_delete(self, evt: MessageDeleteBulk) -> Result<()> {
-        tokio::spawn(message_logging::on_message_bulk_delete(
-            self.clone(),
-            evt.clone(),
-        ));
+        let res = message_logging::on_message_bulk_delete(&self, &evt).await;
         self.storage()
             .redis()
             .messages()
             .bulk_delete(evt.channel_id, evt.ids)
             .await?;
-        Ok(())
+        res
     }
 
     async fn on_guild_create(self, evt: GuildCreate) -> Result<()> {
@@ -636,12 +696,13 @@ impl Client {
     }
 
     async fn on_guild_update(self, evt: GuildUpdate) -> Result<()> {
+        let res = server_logging::on_guild_update(&self, &evt.0).await;
         self.storage()
             .redis()
             .guild(evt.0.id)
             .save_resource(evt.0.id, &evt.0)
             .await?;
-        Ok(())
+        res
     }
 
     async fn on_guild_leave(self, evt: GuildDelete) -> Result<()> {
@@ -660,24 +721,27 @@ impl Client {
     }
 
     async fn on_role_create(self, evt: RoleCreate) -> Result<()> {
+        let res = server_logging::on_role_create(&self, evt.guild_id, &evt.role).await;
         self.storage()
             .redis()
             .guild(evt.guild_id)
             .save_resource(evt.role.id, &evt.role)
             .await?;
-        Ok(())
+        res
     }
 
     async fn on_role_update(self, evt: RoleUpdate) -> Result<()> {
+        let res = server_logging::on_role_update(&self, evt.guild_id, &evt.role).await;
         self.storage()
             .redis()
             .guild(evt.guild_id)
             .save_resource(evt.role.id, &evt.role)
             .await?;
-        Ok(())
+        res
     }
 
     async fn on_role_delete(self, evt: RoleDelete) -> Result<()> {
+        let log = server_logging::on_role_delete(&self, evt.guild_id, evt.role_id).await;
         let res = self
             .storage()
             .execute(hourai_sql::Member::clear_role(evt.guild_id, evt.role_id))
@@ -691,6 +755,7 @@ impl Client {
         self.refresh_bans(evt.guild_id).await?;
         res?;
         res2?;
+        log?;
         Ok(())
     }
 
@@ -702,6 +767,7 @@ impl Client {
         let mut voice_state = self.storage().redis().guild(guild_id).voice_states();
         let channel_id: Option<Id<ChannelMarker>> = voice_state.get_channel(evt.0.user_id).await?;
         announcements::on_voice_update(&self, evt.0.clone(), channel_id).await?;
+        announcements::on_stream_update(&self, &evt.0, channel_id).await?;
         voice_state.save(&evt.0).await?;
         Ok(())
     }
@@ -0,0 +1,313 @@
+use crate::message_logging::{get_output_channel, meets_id_filter};
+use crate::Client;
+use anyhow::Result;
+use hourai::models::{
+    channel::message::allowed_mentions::AllowedMentions,
+    gateway::payload::incoming::{MemberRemove, MemberUpdate},
+    guild::{invite::Invite, member::Member},
+    id::{marker::*, Id},
+    user::User,
+    Snowflake, UserLike,
+};
+use hourai::proto::guild_configs::*;
+use hourai_sql::{Member as MemberRecord, Username};
+use std::collections::HashSet;
+
+async fn get_logging_channel(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    type_config: fn(&LoggingConfig) -> &MemberLoggingConfig,
+) -> Result<Option<Id<ChannelMarker>>> {
+    let config: LoggingConfig = client
+        .storage()
+        .redis()
+        .guild(guild_id)
+        .configs()
+        .get()
+        .await?;
+    let type_config = type_config(&config);
+    if !type_config.get_enabled() || !meets_id_filter(type_config.get_user_filter(), user_id.get())
+    {
+        return Ok(None);
+    }
+    Ok(get_output_channel(
+        &config,
+        type_config.get_output_channel_id(),
+    ))
+}
+
+async fn log(client: &Client, channel_id: Id<ChannelMarker>, content: &str) -> Result<()> {
+    client
+        .http()
+        .create_message(channel_id)
+        .content(content)?
+        .allowed_mentions(Some(&AllowedMentions::builder().build()))
+        .await?;
+    Ok(())
+}
+
+fn describe_user(user: &impl UserLike) -> String {
+    format!("<@{}> ({})", user.id(), user.display_name())
+}
+
+fn describe_roles(role_ids: &[Id<RoleMarker>]) -> String {
+    role_ids
+        .iter()
+        .map(|id| format!("<@&{}>", id))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_invite(invite: &Invite) -> String {
+    let mut description = format!("`{}`", invite.code);
+    if let Some(ref inviter) = invite.inviter {
+        description.push_str(&format!(" by {}", describe_user(inviter)));
+    }
+    description.push_str(&format!(" ({} uses)", invite.uses.unwrap_or(0)));
+    description
+}
+
+/// Determines which invite a member joined with by comparing the use counts of the server's
+/// invites against the counts saved on the last join. Returns None if there are no saved counts or
+/// if more than one invite has been used since.
+async fn find_used_invite(client: &Client, guild_id: Id<GuildMarker>) -> Result<Option<Invite>> {
+    let invites = client.http().guild_invites(guild_id).await?.model().await?;
+    let mut cache = client.storage().redis().guild(guild_id).invites();
+    let previous = cache.fetch().await?;
+    cache
+        .save(
+            invites
+                .iter()
+                .map(|invite| (invite.code.clone(), invite.uses.unwrap_or(0))),
+        )
+        .await?;
+    if previous.is_empty() {
+        return Ok(None);
+    }
+
+    let mut used = invites.into_iter().filter(|invite| {
+        let before = previous.get(&invite.code).copied().unwrap_or(0);
+        invite.uses.unwrap_or(0) > before
+    });
+    Ok(match (used.next(), used.next()) {
+        (Some(invite), None) => Some(invite),
+        _ => None,
+    })
+}
+
+pub(super) async fn on_member_join(client: &Client, member: &Member) -> Result<()> {
+    let output_channel = match get_logging_channel(
+        client,
+        member.guild_id,
+        member.user.id,
+        LoggingConfig::get_member_joins,
+    )
+    .await?
+    {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+
+    // Fetching invites requires the Manage Server permission, so this may fail.
+    let invite = match find_used_invite(client, member.guild_id).await {
+        Ok(Some(invite)) => describe_invite(&invite),
+        Ok(None) => "Unknown".to_owned(),
+        Err(err) => {
+            tracing::debug!(
+                "Failed to find the invite used to join {}: {}",
+                member.guild_id,
+                err
+            );
+            "Unknown".to_owned()
+        }
+    };
+
+    let content = format!(
+        "{} joined. Account created <t:{}:R>. Invite: {}",
+        describe_user(&member.user),
+        member.user.created_at().timestamp(),
+        invite
+    );
+    log(client, output_channel, &content).await
+}
+
+pub(super) async fn on_member_leave(client: &Client, evt: &MemberRemove) -> Result<()> {
+    let output_channel = match get_logging_channel(
+        client,
+        evt.guild_id,
+        evt.user.id,
+        LoggingConfig::get_member_joins,
+    )
+    .await?
+    {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+
+    let member = MemberRecord::fetch(evt.guild_id, evt.user.id)
+        .fetch_optional(client.storage().sql())
+        .await?;
+    let mut content = format!("{} left.", describe_user(&evt.user));
+    if let Some(member) = member {
+        let role_ids: Vec<_> = member.role_ids().collect();
+        if !role_ids.is_empty() {
+            content.push_str(&format!(" Roles: {}", describe_roles(&role_ids)));
+        }
+    }
+    log(client, output_channel, &content).await
+}
+
+/// Logs nickname, role and avatar changes. `before` is the member's record from before the
+/// update was saved.
+pub(super) async fn on_member_update(
+    client: &Client,
+    before: &MemberRecord,
+    evt: &MemberUpdate,
+) -> Result<()> {
+    if before.nickname != evt.nick {
+        let channel = get_logging_channel(
+            client,
+            evt.guild_id,
+            evt.user.id,
+            LoggingConfig::get_nickname_changes,
+        )
+        .await?;
+        if let Some(channel_id) = channel {
+            let content = format!(
+                "{} changed their nickname: {} -> {}",
+                describe_user(&evt.user),
+                describe_nickname(before.nickname.as_deref()),
+                describe_nickname(evt.nick.as_deref())
+            );
+            log(client, channel_id, &content).await?;
+        }
+    }
+
+    let before_roles: HashSet<_> = before.role_ids().collect();
+    let after_roles: HashSet<_> = evt.roles.iter().cloned().collect();
+    if before_roles != after_roles {
+        let channel = get_logging_channel(
+            client,
+            evt.guild_id,
+            evt.user.id,
+            LoggingConfig::get_role_changes,
+        )
+        .await?;
+        if let Some(channel_id) = channel {
+            let mut content = format!("{} roles changed.", describe_user(&evt.user));
+            let mut added: Vec<_> = after_roles.difference(&before_roles).cloned().collect();
+            let mut removed: Vec<_> = before_roles.difference(&after_roles).cloned().collect();
+            added.sort();
+            removed.sort();
+            if !added.is_empty() {
+                content.push_str(&format!(" Added: {}.", describe_roles(&added)));
+            }
+            if !removed.is_empty() {
+                content.push_str(&format!(" Removed: {}.", describe_roles(&removed)));
+            }
+            log(client, channel_id, &content).await?;
+        }
+    }
+
+    let avatar = evt.avatar.map(|hash| hash.to_string());
+    if before.avatar != avatar {
+        let channel = get_logging_channel(
+            client,
+            evt.guild_id,
+            evt.user.id,
+            LoggingConfig::get_username_changes,
+        )
+        .await?;
+        if let Some(channel_id) = channel {
+            let content = match avatar {
+                Some(hash) => format!(
+                    "{} changed their server avatar: \
+                     https://cdn.discordapp.com/guilds/{}/users/{}/avatars/{}.png",
+                    describe_user(&evt.user),
+                    evt.guild_id,
+                    evt.user.id,
+                    hash
+                ),
+                None => format!("{} removed their server avatar.", describe_user(&evt.user)),
+            };
+            log(client, channel_id, &content).await?;
+        }
+    }
+
+    let user_avatar = evt.user.avatar.map(|hash| hash.to_string());
+    if before.user_avatar != user_avatar {
+        let channel = get_logging_channel(
+            client,
+            evt.guild_id,
+            evt.user.id,
+            LoggingConfig::get_username_changes,
+        )
+        .await?;
+        if let Some(channel_id) = channel {
+            let content = match user_avatar {
+                Some(hash) => format!(
+                    "{} changed their avatar: https://cdn.discordapp.com/avatars/{}/{}.png",
+                    describe_user(&evt.user),
+                    evt.user.id,
+                    hash
+                ),
+                None => format!("{} removed their avatar.", describe_user(&evt.user)),
+            };
+            log(client, channel_id, &content).await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn describe_nickname(nickname: Option<&str>) -> String {
+    match nickname {
+        Some(nickname) => format!("`{}`", nickname),
+        None => "*none*".to_owned(),
+    }
+}
+
+pub(super) fn describe_username(username: &Username) -> String {
+    match username.discriminator {
+        Some(discriminator) => format!("{}#{:04}", username.name, discriminator),
+        None => username.name.clone(),
+    }
+}
+
+/// Logs a username change to every server the user is in. Usernames are global, so this should
+/// only be called once per change: by the update that first swaps in the new username.
+pub(super) async fn on_username_change(client: &Client, before: &str, user: &User) -> Result<()> {
+    let guild_ids = MemberRecord::fetch_guild_ids(user.id)
+        .fetch_all(client.storage().sql())
+        .await?;
+    let content = format!(
+        "{} changed their username: `{}` -> `{}`",
+        describe_user(user),
+        before,
+        user.display_name()
+    );
+    for (guild_id,) in guild_ids {
+        let guild_id = Id::new(guild_id as u64);
+        let channel = get_logging_channel(
+            client,
+            guild_id,
+            user.id,
+            LoggingConfig::get_username_changes,
+        )
+        .await;
+        let result = match channel {
+            Ok(Some(channel_id)) => log(client, channel_id, &content).await,
+            Ok(None) => Ok(()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            tracing::error!(
+                "Failed to log username change in guild {}: {}",
+                guild_id,
+                err
+            );
+        }
+    }
+    Ok(())
+}
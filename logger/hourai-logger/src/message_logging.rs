@@ -4,14 +4,23 @@ use anyhow::Result;
 use chrono::Utc;
 use hourai::models::{
     gateway::payload::incoming::{MessageDelete, MessageDeleteBulk},
+    http::attachment::Attachment,
     id::{marker::*, Id},
     util::Timestamp,
     MessageLike, Snowflake, UserLike,
 };
+use hourai::proto::cache::CachedMessageProto;
 use hourai::proto::guild_configs::*;
 use hourai::proto::util::IdFilter;
+use hourai_sql::Member;
+use std::cmp::min;
+use std::collections::HashMap;
 use twilight_util::builder::embed::*;
 
+/// The largest total size of files that can be uploaded in a single message.
+const MAX_UPLOAD_SIZE: u64 = 8 * 1024 * 1024;
+const MAX_FIELD_LENGTH: usize = 1024;
+
 fn message_base_embed(message: &impl MessageLike) -> Result<EmbedBuilder> {
     let author = message.author();
     Ok(EmbedBuilder::new()
@@ -28,6 +37,118 @@ pub(crate) fn message_to_embed(message: &impl MessageLike) -> Result<EmbedBuilde
     Ok(message_base_embed(message)?.description(message.content()))
 }
 
+fn deleted_message_embed(message: &CachedMessageProto) -> Result<EmbedBuilder> {
+    let mut embed = message_to_embed(message)?;
+    let attachments: Vec<_> = message
+        .get_attachments()
+        .iter()
+        .map(|attachment| {
+            let content_type = match attachment.get_content_type() {
+                "" => "unknown type",
+                content_type => content_type,
+            };
+            format!(
+                "[{}]({}) ({}, {})",
+                attachment.get_filename(),
+                attachment.get_url(),
+                content_type,
+                format_size(attachment.get_size())
+            )
+        })
+        .collect();
+    if !attachments.is_empty() {
+        embed = embed.field(EmbedFieldBuilder::new(
+            "Attachments",
+            truncate_field(attachments),
+        ));
+    }
+
+    let embeds: Vec<_> = message
+        .get_embeds()
+        .iter()
+        .map(|embed| {
+            let mut summary = format!("`{}`", embed.get_kind());
+            for part in [embed.get_title(), embed.get_url(), embed.get_image_url()] {
+                if !part.is_empty() {
+                    summary.push(' ');
+                    summary.push_str(part);
+                }
+            }
+            summary
+        })
+        .collect();
+    if !embeds.is_empty() {
+        embed = embed.field(EmbedFieldBuilder::new("Embeds", truncate_field(embeds)));
+    }
+    Ok(embed)
+}
+
+fn format_size(size: u64) -> String {
+    match size {
+        0..=1023 => format!("{} B", size),
+        1024..=1048575 => format!("{:.1} KB", size as f64 / 1024.0),
+        _ => format!("{:.1} MB", size as f64 / 1048576.0),
+    }
+}
+
+fn truncate_field(lines: Vec<String>) -> String {
+    let field = lines.join("\n");
+    if field.chars().count() <= MAX_FIELD_LENGTH {
+        return field;
+    }
+    let mut field: String = field.chars().take(MAX_FIELD_LENGTH - 3).collect();
+    field.push_str("...");
+    field
+}
+
+/// Downloads the images attached to a deleted message so that they can be reuploaded to the log
+/// channel. Images larger than the guild's configured limit are skipped.
+///
+/// This is best-effort: the images are only fetched once the message has been deleted, and Discord
+/// may have already stopped serving them by then. Images that fail to download are still listed
+/// in the log's attachment field.
+async fn download_images(
+    config: &MessageLoggingConfig,
+    message: &CachedMessageProto,
+) -> Vec<Attachment> {
+    let max_size = min(config.get_max_reupload_size(), MAX_UPLOAD_SIZE);
+    let mut total_size = 0;
+    let mut files = Vec::new();
+    for attachment in message.get_attachments() {
+        let size = attachment.get_size();
+        if !attachment.get_content_type().starts_with("image/")
+            || size > max_size
+            || total_size + size > MAX_UPLOAD_SIZE
+        {
+            continue;
+        }
+        // The proxy URL tends to remain valid for longer after the message is deleted.
+        let url = if attachment.has_proxy_url() {
+            attachment.get_proxy_url()
+        } else {
+            attachment.get_url()
+        };
+        match download(url).await {
+            Ok(data) => {
+                total_size += size;
+                let filename = attachment.get_filename().to_owned();
+                files.push(Attachment::from_bytes(filename, data, files.len() as u64));
+            }
+            Err(err) => tracing::warn!("Failed to download deleted attachment {}: {}", url, err),
+        }
+    }
+    files
+}
+
+async fn download(url: &str) -> Result<Vec<u8>> {
+    Ok(reqwest::get(url)
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?
+        .to_vec())
+}
+
 pub(crate) fn message_diff_embed(
     before: &impl MessageLike,
     after: &impl MessageLike,
@@ -37,28 +158,61 @@ pub(crate) fn message_diff_embed(
         .field(EmbedFieldBuilder::new("After", after.content())))
 }
 
-fn meets_id_filter(filter: &IdFilter, id: u64) -> bool {
+pub(crate) fn meets_id_filter(filter: &IdFilter, id: u64) -> bool {
     if filter.denylist.contains(&id) {
         return false;
     }
-    if !filter.allowlist.is_empty() && filter.allowlist.contains(&id) {
-        return true;
+    filter.allowlist.is_empty() || filter.allowlist.contains(&id)
+}
+
+/// Checks a set of IDs against a filter. None of the IDs may be denylisted, and if the allowlist
+/// is not empty, at least one of the IDs must be allowlisted.
+fn meets_any_id_filter(filter: &IdFilter, ids: &[u64]) -> bool {
+    if ids.iter().any(|id| filter.denylist.contains(id)) {
+        return false;
     }
-    return true;
+    filter.allowlist.is_empty() || ids.iter().any(|id| filter.allowlist.contains(id))
 }
 
-fn should_log(config: &MessageLoggingConfig, channel_id: Id<ChannelMarker>) -> bool {
+fn should_log_channel(config: &MessageLoggingConfig, channel_id: Id<ChannelMarker>) -> bool {
     config.get_enabled() && meets_id_filter(config.get_channel_filter(), channel_id.get())
 }
 
-fn get_output_channel(
+async fn should_log(
+    client: &Client,
+    config: &MessageLoggingConfig,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    author_id: Id<UserMarker>,
+) -> Result<bool> {
+    if !should_log_channel(config, channel_id)
+        || !meets_id_filter(config.get_user_filter(), author_id.get())
+    {
+        return Ok(false);
+    }
+
+    let role_filter = config.get_role_filter();
+    if role_filter.allowlist.is_empty() && role_filter.denylist.is_empty() {
+        return Ok(true);
+    }
+    let role_ids: Vec<u64> = Member::fetch(guild_id, author_id)
+        .fetch_optional(client.storage())
+        .await?
+        .map(|member| member.role_ids().map(|id| id.get()).collect())
+        .unwrap_or_default();
+    Ok(meets_any_id_filter(role_filter, &role_ids))
+}
+
+/// Gets the channel a log should be posted to. The type specific output channel takes precedence
+/// over the modlog channel.
+pub(crate) fn get_output_channel(
     config: &LoggingConfig,
-    type_config: &MessageLoggingConfig,
+    output_channel_id: u64,
 ) -> Option<Id<ChannelMarker>> {
-    let id = if config.has_modlog_channel_id() {
+    let id = if output_channel_id != 0 {
+        output_channel_id
+    } else if config.get_modlog_channel_id() != 0 {
         config.get_modlog_channel_id()
-    } else if type_config.has_output_channel_id() {
-        type_config.get_output_channel_id()
     } else {
         return None;
     };
@@ -77,11 +231,16 @@ pub(super) async fn on_message_update(
     let redis = client.storage().redis();
     let config: LoggingConfig = redis.guild(guild_id).configs().get().await?;
     let type_config = config.get_edited_messages();
-    let output_channel = get_output_channel(&config, type_config);
-    if output_channel.is_some() && should_log(type_config, before.channel_id()) {
+    let output_channel = match get_output_channel(&config, type_config.get_output_channel_id()) {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let channel_id = before.channel_id();
+    let author_id = before.author().id();
+    if should_log(&client, type_config, guild_id, channel_id, author_id).await? {
         client
             .http()
-            .create_message(output_channel.unwrap())
+            .create_message(output_channel)
             .content(&format!(
                 "Message by <@{}> edited from <#{}>",
                 before.author().id(),
@@ -100,46 +259,191 @@ pub(super) async fn on_message_delete(client: &mut Client, evt: &MessageDelete)
     let redis = client.storage().redis();
     let config: LoggingConfig = redis.guild(guild_id).configs().get().await?;
     let type_config = config.get_deleted_messages();
-    let output_channel = get_output_channel(&config, type_config);
-    if output_channel.is_some() && should_log(type_config, evt.channel_id) {
+    let output_channel = match get_output_channel(&config, type_config.get_output_channel_id()) {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    if should_log_channel(type_config, evt.channel_id) {
         let cached = redis.messages().fetch(evt.channel_id, evt.id).await?;
         if let Some(msg) = cached {
             if msg.author().bot() {
                 return Ok(());
             }
+            let author_id = Id::new(msg.author().get_id());
+            if !should_log(client, type_config, guild_id, evt.channel_id, author_id).await? {
+                return Ok(());
+            }
+            let files = download_images(type_config, &msg).await;
             client
                 .http()
-                .create_message(output_channel.unwrap())
+                .create_message(output_channel)
                 .content(&format!(
                     "Message by <@{}> deleted from <#{}>",
                     msg.author().get_id(),
                     msg.get_channel_id()
                 ))?
-                .embeds(&vec![message_to_embed(&msg)?
+                .embeds(&vec![deleted_message_embed(&msg)?
                     .color(0x992d22) // Dark red
                     .build()])?
+                .attachments(&files)?
                 .await?;
         }
     }
     Ok(())
 }
 
-pub(super) async fn on_message_bulk_delete(client: Client, evt: MessageDeleteBulk) -> Result<()> {
+pub(super) async fn on_message_bulk_delete(client: &Client, evt: &MessageDeleteBulk) -> Result<()> {
     let guild_id = evt.guild_id.ok_or_else(|| anyhow!("Not in guild."))?;
     let redis = client.storage().redis();
     let config: LoggingConfig = redis.guild(guild_id).configs().get().await?;
     let type_config = config.get_deleted_messages();
-    let output_channel = get_output_channel(&config, type_config);
-    if output_channel.is_some() && should_log(type_config, evt.channel_id) {
-        client
-            .http()
-            .create_message(output_channel.unwrap())
-            .content(&format!(
-                "{} messages bulk deleted from <#{}>",
-                evt.ids.len(),
-                evt.channel_id
-            ))?
-            .await?;
+    let output_channel = match get_output_channel(&config, type_config.get_output_channel_id()) {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    if !should_log_channel(type_config, evt.channel_id) {
+        return Ok(());
+    }
+
+    let cached = redis
+        .messages()
+        .fetch_all(evt.channel_id, evt.ids.iter().cloned())
+        .await?;
+    let uncached = evt.ids.len() - cached.len();
+
+    // Apply the same filters as single message deletions, checking each author only once.
+    let mut authors: HashMap<u64, bool> = HashMap::new();
+    let mut messages = Vec::with_capacity(cached.len());
+    for msg in cached {
+        if msg.author().bot() {
+            continue;
+        }
+        let author_id = msg.author().get_id();
+        let logged = match authors.get(&author_id) {
+            Some(logged) => *logged,
+            None => {
+                let logged = should_log(
+                    client,
+                    type_config,
+                    guild_id,
+                    evt.channel_id,
+                    Id::new(author_id),
+                )
+                .await?;
+                authors.insert(author_id, logged);
+                logged
+            }
+        };
+        if logged {
+            messages.push(msg);
+        }
+    }
+    messages.sort_by_key(|msg| msg.get_id());
+    let filtered = evt.ids.len() - uncached - messages.len();
+    let summary = format!(
+        "{} messages bulk deleted from <#{}>. {} of them were not cached and {} were from bots \
+         or excluded by the logging filters.",
+        evt.ids.len(),
+        evt.channel_id,
+        uncached,
+        filtered
+    );
+    let create = client
+        .http()
+        .create_message(output_channel)
+        .content(&summary)?;
+    if messages.is_empty() {
+        create.await?;
+        return Ok(());
     }
+
+    let filename = format!(
+        "deleted-messages-{}-{}",
+        evt.channel_id,
+        Utc::now().timestamp()
+    );
+    let files = [
+        Attachment::from_bytes(
+            format!("{}.txt", filename),
+            text_transcript(&messages).into_bytes(),
+            0,
+        ),
+        Attachment::from_bytes(
+            format!("{}.html", filename),
+            html_transcript(evt.channel_id, &messages).into_bytes(),
+            1,
+        ),
+    ];
+    create.attachments(&files)?.await?;
     Ok(())
 }
+
+fn format_timestamp(message: &CachedMessageProto) -> String {
+    message
+        .created_at()
+        .format("%Y-%m-%d %H:%M:%S UTC")
+        .to_string()
+}
+
+fn text_transcript(messages: &[CachedMessageProto]) -> String {
+    let mut transcript = String::new();
+    for message in messages {
+        let author = message.author();
+        transcript.push_str(&format!(
+            "[{}] {} ({}): {}\n",
+            format_timestamp(message),
+            author.display_name(),
+            author.get_id(),
+            message.content()
+        ));
+        for attachment in message.get_attachments() {
+            transcript.push_str(&format!("    Attachment: {}\n", attachment.get_url()));
+        }
+    }
+    transcript
+}
+
+fn html_transcript(channel_id: Id<ChannelMarker>, messages: &[CachedMessageProto]) -> String {
+    let mut transcript = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Deleted messages from {}</title>\n</head>\n<body>\n<table>\n\
+         <tr><th>Timestamp</th><th>Author</th><th>Content</th></tr>\n",
+        channel_id
+    );
+    for message in messages {
+        let author = message.author();
+        let mut content = escape_html(message.content()).replace('\n', "<br>");
+        for attachment in message.get_attachments() {
+            let url = escape_html(attachment.get_url());
+            content.push_str(&format!(
+                "<br><a href=\"{}\">{}</a>",
+                url,
+                escape_html(attachment.get_filename())
+            ));
+        }
+        transcript.push_str(&format!(
+            "<tr><td>{}</td><td title=\"{}\">{}</td><td>{}</td></tr>\n",
+            format_timestamp(message),
+            author.get_id(),
+            escape_html(&author.display_name()),
+            content
+        ));
+    }
+    transcript.push_str("</table>\n</body>\n</html>\n");
+    transcript
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for chr in text.chars() {
+        match chr {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(chr),
+        }
+    }
+    escaped
+}
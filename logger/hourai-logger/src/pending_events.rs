@@ -1,28 +1,58 @@
 use crate::utils;
 use anyhow::Result;
+use chrono::Utc;
 use futures::stream::StreamExt;
 use hourai_sql::{Executor, PendingAction, PendingDeescalation};
 use hourai_storage::{actions::ActionExecutor, escalation::EscalationManager};
 use tokio::time::{Duration, Instant};
 
 const CYCLE_DURATION: Duration = Duration::from_secs(1);
+/// The maximum number of pending actions claimed in a single cycle.
+const ACTION_BATCH_SIZE: i64 = 100;
+/// How long a claimed action is hidden from other workers. If the worker dies before
+/// finishing the action, it will be retried after the lease expires.
+const ACTION_LEASE: Duration = Duration::from_secs(5 * 60);
+/// The number of attempts made before an action is moved to the dead-letter table.
+const ACTION_MAX_ATTEMPTS: i32 = 8;
+/// The base delay for retrying failed actions. Doubles with every failed attempt.
+const ACTION_RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+
+/// Records the guild and user of pending actions that were scheduled before they were stored
+/// alongside the action, so that they show up in `/scheduled` and `/role timed`.
+pub async fn backfill_pending_action_owners(executor: ActionExecutor) -> Result<()> {
+    let sql = executor.storage().sql();
+    let unowned = PendingAction::fetch_unowned().fetch_all(sql).await?;
+    let mut updated = 0;
+    for pending in unowned {
+        if pending.action().get_guild_id() != 0 {
+            sql.execute(pending.set_owner()).await?;
+            updated += 1;
+        }
+    }
+    if updated > 0 {
+        tracing::info!("Backfilled the owners of {} pending actions", updated);
+    }
+    Ok(())
+}
 
 pub async fn run_pending_actions(executor: ActionExecutor) {
     loop {
         let next = Instant::now() + CYCLE_DURATION;
-        let mut pending = PendingAction::fetch_expired().fetch(executor.storage().sql());
-        while let Some(item) = pending.next().await {
-            match item {
-                Ok(action) => {
+        let lease_expiration = Utc::now() + chrono::Duration::from_std(ACTION_LEASE).unwrap();
+        let claimed = PendingAction::claim_expired(ACTION_BATCH_SIZE, lease_expiration)
+            .fetch_all(executor.storage().sql())
+            .await;
+        match claimed {
+            Ok(actions) => {
+                for action in actions {
                     tokio::spawn(utils::log_error(
                         "running pending action",
                         run_action(executor.clone(), action),
                     ));
                 }
-                Err(err) => {
-                    tracing::error!("Error while fetching pending actions: {}", err);
-                    break;
-                }
+            }
+            Err(err) => {
+                tracing::error!("Error while claiming pending actions: {}", err);
             }
         }
         tokio::time::sleep_until(next).await;
@@ -39,17 +69,42 @@ fn is_client_error(err: &anyhow::Error) -> bool {
     return false;
 }
 
+fn retry_delay(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(1, ACTION_MAX_ATTEMPTS) - 1;
+    ACTION_RETRY_BASE_DELAY * 2u32.pow(exponent as u32)
+}
+
 async fn run_action(executor: ActionExecutor, pending: PendingAction) -> Result<()> {
     tracing::debug!("Running pending action: {:?}", pending.action());
-    if let Err(err) = executor.execute_action(pending.action()).await {
-        if !is_client_error(&err) {
-            return Err(err);
-        } else {
-            tracing::error!("Client Error while running pending actions: {}", err);
+    let query = match executor.execute_action(pending.action()).await {
+        Ok(()) => {
+            tracing::info!("Ran pending action: {:?}", pending.action());
+            pending.delete()
         }
-    }
-    executor.storage().sql().execute(pending.delete()).await?;
-    tracing::info!("Ran pending action: {:?}", pending.action());
+        // Client errors will not succeed on retry.
+        Err(err) if is_client_error(&err) || pending.attempts() >= ACTION_MAX_ATTEMPTS => {
+            tracing::error!(
+                "Pending action failed after {} attempts, moving to dead letter table: {}",
+                pending.attempts(),
+                err
+            );
+            pending.dead_letter(err.to_string())
+        }
+        Err(err) => {
+            let delay = retry_delay(pending.attempts());
+            tracing::warn!(
+                "Pending action failed on attempt {}, retrying in {:?}: {}",
+                pending.attempts(),
+                delay,
+                err
+            );
+            pending.retry(
+                Utc::now() + chrono::Duration::from_std(delay)?,
+                err.to_string(),
+            )
+        }
+    };
+    executor.storage().sql().execute(query).await?;
     Ok(())
 }
 
@@ -47,39 +47,30 @@ async fn get_verification_role(
     }
 }
 
-pub async fn on_member_join(client: &Client, member: &Member) -> Result<()> {
-    let guild_id = member.guild_id;
-    let user_id = member.user.id;
-
-    let bot_roles = match get_roles(client.storage(), guild_id, client.user_id()).await {
-        Ok(roles) => roles,
-        Err(hourai_sql::Error::RowNotFound) => return Ok(()),
-        Err(err) => anyhow::bail!(err),
-    };
-
-    let redis = client.storage().redis();
+/// Finds the roles that would be restored to a user when they join the server: the restorable
+/// roles they last had that are below the bot's highest role, except the verification role.
+pub async fn find_restorable_roles(
+    storage: &Storage,
+    guild_id: Id<GuildMarker>,
+    bot_roles: &[Id<RoleMarker>],
+    user_id: Id<UserMarker>,
+) -> Result<Vec<Id<RoleMarker>>> {
+    let redis = storage.redis();
     let mut guild = redis.guild(guild_id);
-    let perms = guild
-        .guild_permissions(client.user_id(), bot_roles.iter().cloned())
-        .await?;
-    if !perms.contains(Permissions::MANAGE_ROLES) {
-        return Ok(());
-    }
-
-    let user_roles = match get_roles(client.storage(), guild_id, user_id).await {
+    let user_roles = match get_roles(storage, guild_id, user_id).await {
         Ok(roles) => guild.role_set(&roles).await?,
-        Err(hourai_sql::Error::RowNotFound) => return Ok(()),
+        Err(hourai_sql::Error::RowNotFound) => return Ok(Vec::new()),
         Err(err) => anyhow::bail!(err),
     };
 
     let max_role = guild
-        .role_set(&bot_roles)
+        .role_set(bot_roles)
         .await?
         .highest()
         .cloned()
         .unwrap_or_else(|| CachedRoleProto::default());
 
-    let flags = get_role_flags(client.storage(), guild_id).await?;
+    let flags = get_role_flags(storage, guild_id).await?;
     let mut restorable: Vec<Id<RoleMarker>> = user_roles
         .iter()
         .filter(|role| {
@@ -93,10 +84,47 @@ pub async fn on_member_join(client: &Client, member: &Member) -> Result<()> {
         .collect();
 
     // Do not give out the verification role if it is enabled.
-    if let Some(role) = get_verification_role(client.storage(), guild_id).await? {
+    if let Some(role) = get_verification_role(storage, guild_id).await? {
         restorable.retain(|id| *id != role);
     }
 
+    Ok(restorable)
+}
+
+/// Gets the roles of the bot in a server, or an empty list if the bot is not in it.
+pub async fn get_bot_roles(
+    storage: &Storage,
+    guild_id: Id<GuildMarker>,
+    bot_id: Id<UserMarker>,
+) -> Result<Vec<Id<RoleMarker>>> {
+    match get_roles(storage, guild_id, bot_id).await {
+        Ok(roles) => Ok(roles),
+        Err(hourai_sql::Error::RowNotFound) => Ok(Vec::new()),
+        Err(err) => anyhow::bail!(err),
+    }
+}
+
+pub async fn on_member_join(client: &Client, member: &Member) -> Result<()> {
+    let guild_id = member.guild_id;
+    let user_id = member.user.id;
+
+    let bot_roles = match get_roles(client.storage(), guild_id, client.user_id()).await {
+        Ok(roles) => roles,
+        Err(hourai_sql::Error::RowNotFound) => return Ok(()),
+        Err(err) => anyhow::bail!(err),
+    };
+
+    let perms = client
+        .storage()
+        .redis()
+        .guild(guild_id)
+        .guild_permissions(client.user_id(), bot_roles.iter().cloned())
+        .await?;
+    if !perms.contains(Permissions::MANAGE_ROLES) {
+        return Ok(());
+    }
+
+    let restorable = find_restorable_roles(client.storage(), guild_id, &bot_roles, user_id).await?;
     if restorable.is_empty() {
         return Ok(());
     }
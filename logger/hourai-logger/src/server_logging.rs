@@ -0,0 +1,494 @@
+use crate::message_logging::get_output_channel;
+use crate::Client;
+use anyhow::Result;
+use chrono::Utc;
+use hourai::models::{
+    channel::{message::allowed_mentions::AllowedMentions, Channel},
+    guild::{Guild, PartialGuild, Permissions, Role},
+    id::{marker::*, Id},
+};
+use hourai::proto::{cache::*, guild_configs::*};
+use hourai_redis::ToProto;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+
+const DISCORD_EPOCH: u64 = 1420070400000;
+const AUDIT_LOG_LIMIT: u16 = 10;
+/// Audit log entries older than this are assumed to be for a different change.
+const MAX_AUDIT_LOG_AGE_MS: u64 = 30_000;
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// A list of human readable changes made to a channel, role or the server.
+struct Changes {
+    guild_id: Id<GuildMarker>,
+    lines: Vec<String>,
+}
+
+impl Changes {
+    fn new(guild_id: Id<GuildMarker>) -> Self {
+        Self {
+            guild_id,
+            lines: Vec::new(),
+        }
+    }
+
+    fn field(&mut self, name: &str, before: impl Display, after: impl Display) {
+        let (before, after) = (before.to_string(), after.to_string());
+        if before != after {
+            self.lines.push(format!(
+                "{}: {} -> {}",
+                name,
+                describe_value(&before),
+                describe_value(&after)
+            ));
+        }
+    }
+
+    fn channel(&mut self, name: &str, before: u64, after: u64) {
+        if before != after {
+            self.lines.push(format!(
+                "{}: {} -> {}",
+                name,
+                describe_channel(before),
+                describe_channel(after)
+            ));
+        }
+    }
+
+    fn permissions(&mut self, name: &str, before: u64, after: u64) {
+        let before = Permissions::from_bits_truncate(before);
+        let after = Permissions::from_bits_truncate(after);
+        let mut changes = Vec::new();
+        if !(after - before).is_empty() {
+            changes.push(format!("added {}", describe_permissions(after - before)));
+        }
+        if !(before - after).is_empty() {
+            changes.push(format!("removed {}", describe_permissions(before - after)));
+        }
+        if !changes.is_empty() {
+            self.lines.push(format!("{}: {}", name, changes.join("; ")));
+        }
+    }
+
+    fn overwrites(
+        &mut self,
+        before: &[CachedPermissionOverwriteProto],
+        after: &[CachedPermissionOverwriteProto],
+    ) {
+        let before: HashMap<u64, _> = before.iter().map(|o| (o.get_id(), o)).collect();
+        let after: HashMap<u64, _> = after.iter().map(|o| (o.get_id(), o)).collect();
+        let ids: BTreeSet<u64> = before.keys().chain(after.keys()).cloned().collect();
+        for id in ids {
+            match (before.get(&id), after.get(&id)) {
+                (Some(before), Some(after)) => {
+                    let target = self.overwrite_target(after);
+                    self.permissions(
+                        &format!("Allowed for {}", target),
+                        before.get_allow(),
+                        after.get_allow(),
+                    );
+                    self.permissions(
+                        &format!("Denied for {}", target),
+                        before.get_deny(),
+                        after.get_deny(),
+                    );
+                }
+                (None, Some(after)) => {
+                    let target = self.overwrite_target(after);
+                    self.lines.push(format!("Added overwrite for {}", target));
+                    self.permissions(&format!("Allowed for {}", target), 0, after.get_allow());
+                    self.permissions(&format!("Denied for {}", target), 0, after.get_deny());
+                }
+                (Some(before), None) => {
+                    let target = self.overwrite_target(before);
+                    self.lines.push(format!("Removed overwrite for {}", target));
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    fn overwrite_target(&self, overwrite: &CachedPermissionOverwriteProto) -> String {
+        if overwrite.get_member() {
+            format!("<@{}>", overwrite.get_id())
+        } else if overwrite.get_id() == self.guild_id.get() {
+            "@everyone".to_owned()
+        } else {
+            format!("<@&{}>", overwrite.get_id())
+        }
+    }
+}
+
+fn describe_value(value: &str) -> String {
+    if value.is_empty() {
+        "*none*".to_owned()
+    } else {
+        format!("`{}`", value)
+    }
+}
+
+fn describe_channel(channel_id: u64) -> String {
+    if channel_id == 0 {
+        "*none*".to_owned()
+    } else {
+        format!("<#{}>", channel_id)
+    }
+}
+
+fn describe_permissions(permissions: Permissions) -> String {
+    format!("{:?}", permissions).replace(" | ", ", ")
+}
+
+fn describe_actor(actor: Option<Id<UserMarker>>) -> String {
+    match actor {
+        Some(user_id) => format!(" by <@{}>", user_id),
+        None => String::new(),
+    }
+}
+
+async fn get_logging_channel(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+) -> Result<Option<Id<ChannelMarker>>> {
+    let config: LoggingConfig = client
+        .storage()
+        .redis()
+        .guild(guild_id)
+        .configs()
+        .get()
+        .await?;
+    let type_config = config.get_server_changes();
+    if !type_config.get_enabled() {
+        return Ok(None);
+    }
+    Ok(get_output_channel(
+        &config,
+        type_config.get_output_channel_id(),
+    ))
+}
+
+/// Finds the user responsible for a recent change to a channel, role or the server from the
+/// server's audit log. Returns None if the bot cannot view the audit log or no recent entry
+/// targets the changed resource.
+async fn find_actor(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    target_id: u64,
+) -> Option<Id<UserMarker>> {
+    match fetch_actor(client, guild_id, target_id).await {
+        Ok(actor) => actor,
+        Err(err) => {
+            tracing::debug!("Failed to fetch the audit log for {}: {}", guild_id, err);
+            None
+        }
+    }
+}
+
+async fn fetch_actor(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    target_id: u64,
+) -> Result<Option<Id<UserMarker>>> {
+    let audit_log = client
+        .http()
+        .audit_log(guild_id)
+        .limit(AUDIT_LOG_LIMIT)?
+        .await?
+        .model()
+        .await?;
+    let now = Utc::now().timestamp_millis() as u64;
+    Ok(audit_log
+        .entries
+        .iter()
+        .filter(|entry| {
+            let created_at = (entry.id.get() >> 22) + DISCORD_EPOCH;
+            now.saturating_sub(created_at) < MAX_AUDIT_LOG_AGE_MS
+        })
+        .find(|entry| entry.target_id.map(|id| id.get()) == Some(target_id))
+        .and_then(|entry| entry.user_id))
+}
+
+async fn log(
+    client: &Client,
+    output_channel: Id<ChannelMarker>,
+    header: String,
+    changes: Vec<String>,
+) -> Result<()> {
+    let mut content = header;
+    for change in changes {
+        let line = format!("\n- {}", change);
+        if content.len() + line.len() > MAX_MESSAGE_LENGTH - 4 {
+            content.push_str("\n...");
+            break;
+        }
+        content.push_str(&line);
+    }
+    client
+        .http()
+        .create_message(output_channel)
+        .content(&content)?
+        .allowed_mentions(Some(&AllowedMentions::builder().build()))
+        .await?;
+    Ok(())
+}
+
+pub(super) async fn on_channel_create(client: &Client, channel: &Channel) -> Result<()> {
+    let guild_id = match channel.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let output_channel = match get_logging_channel(client, guild_id).await? {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let actor = find_actor(client, guild_id, channel.id.get()).await;
+    let header = format!(
+        "Channel <#{}> (`{}`) created{}.",
+        channel.id,
+        channel.name.as_deref().unwrap_or_default(),
+        describe_actor(actor)
+    );
+    let mut changes = Changes::new(guild_id);
+    changes.overwrites(&[], channel.to_proto().get_permission_overwrites());
+    log(client, output_channel, header, changes.lines).await
+}
+
+/// Logs the changes made to a channel. Must be called before the cached channel is updated.
+pub(super) async fn on_channel_update(client: &Client, channel: &Channel) -> Result<()> {
+    let guild_id = match channel.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let output_channel = match get_logging_channel(client, guild_id).await? {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let before = client
+        .storage()
+        .redis()
+        .guild(guild_id)
+        .fetch_resource::<Channel>(channel.id)
+        .await?;
+    let before = match before {
+        Some(before) => before,
+        None => return Ok(()),
+    };
+
+    let after = channel.to_proto();
+    let mut changes = Changes::new(guild_id);
+    changes.field("Name", before.get_name(), after.get_name());
+    changes.field("Topic", before.get_topic(), after.get_topic());
+    changes.channel("Category", before.get_parent_id(), after.get_parent_id());
+    changes.field("NSFW", before.get_nsfw(), after.get_nsfw());
+    changes.overwrites(
+        before.get_permission_overwrites(),
+        after.get_permission_overwrites(),
+    );
+    // Reordering channels updates every channel whose position changed, which are not logged.
+    if changes.lines.is_empty() {
+        return Ok(());
+    }
+
+    let actor = find_actor(client, guild_id, channel.id.get()).await;
+    let header = format!(
+        "Channel <#{}> updated{}:",
+        channel.id,
+        describe_actor(actor)
+    );
+    log(client, output_channel, header, changes.lines).await
+}
+
+/// Logs the deletion of a channel. Must be called before the cached channel is deleted.
+pub(super) async fn on_channel_delete(client: &Client, channel: &Channel) -> Result<()> {
+    let guild_id = match channel.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let output_channel = match get_logging_channel(client, guild_id).await? {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let actor = find_actor(client, guild_id, channel.id.get()).await;
+    let header = format!(
+        "Channel `{}` ({}) deleted{}.",
+        channel.name.as_deref().unwrap_or_default(),
+        channel.id,
+        describe_actor(actor)
+    );
+    log(client, output_channel, header, Vec::new()).await
+}
+
+pub(super) async fn on_role_create(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    role: &Role,
+) -> Result<()> {
+    let output_channel = match get_logging_channel(client, guild_id).await? {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let actor = find_actor(client, guild_id, role.id.get()).await;
+    let header = format!(
+        "Role <@&{}> (`{}`) created{}.",
+        role.id,
+        role.name,
+        describe_actor(actor)
+    );
+    let mut changes = Changes::new(guild_id);
+    changes.permissions("Permissions", 0, role.permissions.bits());
+    log(client, output_channel, header, changes.lines).await
+}
+
+/// Logs the changes made to a role. Must be called before the cached role is updated.
+pub(super) async fn on_role_update(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    role: &Role,
+) -> Result<()> {
+    let output_channel = match get_logging_channel(client, guild_id).await? {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let before = client
+        .storage()
+        .redis()
+        .guild(guild_id)
+        .fetch_resource::<Role>(role.id)
+        .await?;
+    let before = match before {
+        Some(before) => before,
+        None => return Ok(()),
+    };
+
+    let after = role.to_proto();
+    let mut changes = Changes::new(guild_id);
+    changes.field("Name", before.get_name(), after.get_name());
+    changes.field(
+        "Color",
+        format!("#{:06x}", before.get_color()),
+        format!("#{:06x}", after.get_color()),
+    );
+    changes.field("Hoisted", before.get_hoist(), after.get_hoist());
+    changes.field(
+        "Mentionable",
+        before.get_mentionable(),
+        after.get_mentionable(),
+    );
+    changes.permissions(
+        "Permissions",
+        before.get_permissions(),
+        after.get_permissions(),
+    );
+    // Reordering roles updates every role whose position changed, which are not logged.
+    if changes.lines.is_empty() {
+        return Ok(());
+    }
+
+    let actor = find_actor(client, guild_id, role.id.get()).await;
+    let header = format!("Role <@&{}> updated{}:", role.id, describe_actor(actor));
+    log(client, output_channel, header, changes.lines).await
+}
+
+/// Logs the deletion of a role. Must be called before the cached role is deleted.
+pub(super) async fn on_role_delete(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    role_id: Id<RoleMarker>,
+) -> Result<()> {
+    let output_channel = match get_logging_channel(client, guild_id).await? {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let before = client
+        .storage()
+        .redis()
+        .guild(guild_id)
+        .fetch_resource::<Role>(role_id)
+        .await?;
+    let name = before
+        .map(|role| role.get_name().to_owned())
+        .unwrap_or_else(|| "Unknown".to_owned());
+    let actor = find_actor(client, guild_id, role_id.get()).await;
+    let header = format!(
+        "Role `{}` ({}) deleted{}.",
+        name,
+        role_id,
+        describe_actor(actor)
+    );
+    log(client, output_channel, header, Vec::new()).await
+}
+
+/// Logs the changes made to the server's settings. Must be called before the cached server is
+/// updated.
+pub(super) async fn on_guild_update(client: &Client, guild: &PartialGuild) -> Result<()> {
+    let output_channel = match get_logging_channel(client, guild.id).await? {
+        Some(channel_id) => channel_id,
+        None => return Ok(()),
+    };
+    let before = client
+        .storage()
+        .redis()
+        .guild(guild.id)
+        .fetch_resource::<Guild>(guild.id)
+        .await?;
+    let before = match before {
+        Some(before) => before,
+        None => return Ok(()),
+    };
+
+    let after = guild.to_proto();
+    let mut changes = Changes::new(guild.id);
+    changes.field("Name", before.get_name(), after.get_name());
+    changes.field(
+        "Description",
+        before.get_description(),
+        after.get_description(),
+    );
+    if before.get_icon() != after.get_icon() {
+        changes.lines.push("Icon changed".to_owned());
+    }
+    if before.get_owner_id() != after.get_owner_id() {
+        changes.lines.push(format!(
+            "Owner: <@{}> -> <@{}>",
+            before.get_owner_id(),
+            after.get_owner_id()
+        ));
+    }
+    changes.field(
+        "Vanity URL",
+        before.get_vanity_url_code(),
+        after.get_vanity_url_code(),
+    );
+    changes.channel(
+        "AFK channel",
+        before.get_afk_channel_id(),
+        after.get_afk_channel_id(),
+    );
+    changes.channel(
+        "System channel",
+        before.get_system_channel_id(),
+        after.get_system_channel_id(),
+    );
+    changes.channel(
+        "Rules channel",
+        before.get_rules_channel_id(),
+        after.get_rules_channel_id(),
+    );
+    changes.field(
+        "Verification level",
+        before.get_verification_level(),
+        after.get_verification_level(),
+    );
+    changes.field(
+        "Explicit content filter",
+        before.get_explicit_content_filter(),
+        after.get_explicit_content_filter(),
+    );
+    if changes.lines.is_empty() {
+        return Ok(());
+    }
+
+    let actor = find_actor(client, guild.id, guild.id.get()).await;
+    let header = format!("Server settings updated{}:", describe_actor(actor));
+    log(client, output_channel, header, changes.lines).await
+}
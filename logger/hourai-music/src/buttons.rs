@@ -13,6 +13,9 @@ const STOP_BUTTON: &str = "⏹️";
 const NEXT_TRACK_BUTTON: &str = "⏭️";
 const VOLUME_DOWN_BUTTON: &str = "🔉";
 const VOLUME_UP_BUTTON: &str = "🔊";
+const LOOP_BUTTON: &str = "🔁";
+const REWIND_BUTTON: &str = "⏪";
+const FORWARD_BUTTON: &str = "⏩";
 
 #[inline(always)]
 pub fn previous_button(ui_type: MusicUIType) -> Component {
@@ -73,6 +76,29 @@ pub fn volume_up_button(ui_type: MusicUIType) -> Component {
     )
 }
 
+#[inline(always)]
+pub fn loop_button(ui_type: MusicUIType) -> Component {
+    create_button(LOOP_BUTTON, ui_type, MusicButtonOption::MUSIC_BUTTON_LOOP)
+}
+
+#[inline(always)]
+pub fn rewind_button(ui_type: MusicUIType) -> Component {
+    create_button(
+        REWIND_BUTTON,
+        ui_type,
+        MusicButtonOption::MUSIC_BUTTON_REWIND,
+    )
+}
+
+#[inline(always)]
+pub fn forward_button(ui_type: MusicUIType) -> Component {
+    create_button(
+        FORWARD_BUTTON,
+        ui_type,
+        MusicButtonOption::MUSIC_BUTTON_FORWARD,
+    )
+}
+
 fn create_button(emoji: &str, ui_type: MusicUIType, button: MusicButtonOption) -> Component {
     let mut proto = MessageComponentProto::new();
     proto.mut_music_button().set_field_type(ui_type);
@@ -1,4 +1,4 @@
-use crate::{buttons, prelude::*, track::Track, Client};
+use crate::{buttons, player, prelude::*, track::Track, Client};
 use anyhow::Result;
 use hourai::{
     interactions::{CommandContext, InteractionContext, Response},
@@ -130,15 +130,24 @@ impl EmbedUIBuilder for NowPlayingUI {
     }
 
     fn build_components(&self, _: &EmbedUI<Self>) -> Result<Vec<Component>> {
-        Ok(vec![Component::ActionRow(ActionRow {
-            components: vec![
-                buttons::volume_down_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
-                buttons::volume_up_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
-                buttons::play_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
-                buttons::stop_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
-                buttons::skip_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
-            ],
-        })])
+        Ok(vec![
+            Component::ActionRow(ActionRow {
+                components: vec![
+                    buttons::volume_down_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
+                    buttons::volume_up_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
+                    buttons::play_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
+                    buttons::stop_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
+                    buttons::skip_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
+                ],
+            }),
+            Component::ActionRow(ActionRow {
+                components: vec![
+                    buttons::rewind_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
+                    buttons::forward_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
+                    buttons::loop_button(MusicUIType::MUSIC_UI_TYPE_NOW_PLAYING),
+                ],
+            }),
+        ])
     }
 }
 
@@ -151,6 +160,7 @@ fn build_np_embed<T: EmbedUIBuilder>(ui: &EmbedUI<T>) -> Result<Embed> {
         Some(player) => player.volume(),
         None => return not_playing_embed(),
     };
+    let loop_mode = ui.client.loop_mode(ui.guild_id).unwrap_or_default();
 
     Ok(EmbedBuilder::new()
         .author(
@@ -160,7 +170,11 @@ fn build_np_embed<T: EmbedUIBuilder>(ui: &EmbedUI<T>) -> Result<Embed> {
         .title(track.info.title.unwrap_or_else(|| "Unknown".to_owned()))
         .description(build_progress_bar(&ui))
         .url(track.info.uri.clone())
-        .footer(EmbedFooterBuilder::new(format!("Volume: {}", volume)))
+        .footer(EmbedFooterBuilder::new(format!(
+            "Volume: {} | Loop: {}",
+            volume,
+            player::loop_mode_name(loop_mode)
+        )))
         .build())
 }
 
@@ -245,10 +259,11 @@ impl EmbedUIBuilder for QueueUI {
 }
 
 fn build_progress_bar<T: EmbedUIBuilder + Default>(ui: &EmbedUI<T>) -> String {
-    let (pos, paused) = match ui.client.lavalink.players().get(&ui.guild_id) {
-        Some(player) => (player.position(), player.paused()),
-        None => (0, true),
+    let paused = match ui.client.lavalink.players().get(&ui.guild_id) {
+        Some(player) => player.paused(),
+        None => true,
     };
+    let pos = ui.client.position(ui.guild_id).unwrap_or(0);
 
     let length = match ui.client.currently_playing(ui.guild_id) {
         Some(track) => track.info.length,
@@ -1,20 +1,38 @@
 use crate::prelude::*;
-use crate::{interaction_ui::*, player::PlayerState, queue::MusicQueue, track::Track, Client};
+use crate::{
+    interaction_ui::*,
+    player::{self, Advance, PlayerState},
+    queue::MusicQueue,
+    track::Track,
+    Client,
+};
 use anyhow::{bail, Result};
 use hourai::{
     interactions::*,
     models::{
         id::{
-            marker::{ChannelMarker, GuildMarker, RoleMarker},
+            marker::{ChannelMarker, GuildMarker, RoleMarker, UserMarker},
             Id,
         },
         Snowflake, UserLike,
     },
-    proto::{guild_configs::MusicConfig, message_components::MusicButtonOption},
+    proto::{
+        cache::CachedUserProto,
+        guild_configs::MusicConfig,
+        message_components::MusicButtonOption,
+        music_bot::{LoopMode, PlaylistProto, TrackProto},
+    },
 };
+use hourai_sql::{Playlist, PlaylistOwner};
 use std::{collections::HashSet, convert::TryFrom};
 use twilight_lavalink::http::LoadType;
 
+const MAX_PLAYLIST_NAME_LENGTH: usize = 100;
+const MAX_PLAYLIST_TRACKS: usize = 200;
+const MAX_PLAYLISTS: i64 = 25;
+const MAX_RESPONSE_LENGTH: usize = 1900;
+const SEEK_BUTTON_SECONDS: i64 = 10;
+
 macro_rules! get_player {
     ($client:expr, $guild_id: expr) => {
         $client.lavalink.players().get($guild_id).unwrap()
@@ -69,6 +87,40 @@ pub async fn handle_command(client: Client, ctx: CommandContext) -> Result<()> {
             ctx.defer().await?;
             volume(&client, &ctx).await
         }
+        Command::SubCommand("music", "seek") => {
+            ctx.defer().await?;
+            seek_command(&client, &ctx).await
+        }
+        Command::SubCommand("music", "forward") => {
+            ctx.defer().await?;
+            let seconds = seek_seconds(&ctx)?;
+            seek(&client, &ctx, |position| position + seconds * 1000).await
+        }
+        Command::SubCommand("music", "rewind") => {
+            ctx.defer().await?;
+            let seconds = seek_seconds(&ctx)?;
+            seek(&client, &ctx, |position| position - seconds * 1000).await
+        }
+        Command::SubCommand("music", "loop") => {
+            ctx.defer().await?;
+            loop_command(&client, &ctx).await
+        }
+        Command::SubGroupCommand("music", "playlist", "save") => {
+            ctx.defer().await?;
+            playlist_save(&client, &ctx).await
+        }
+        Command::SubGroupCommand("music", "playlist", "load") => {
+            ctx.defer().await?;
+            playlist_load(&client, &ctx).await
+        }
+        Command::SubGroupCommand("music", "playlist", "list") => {
+            ctx.defer().await?;
+            playlist_list(&client, &ctx).await
+        }
+        Command::SubGroupCommand("music", "playlist", "delete") => {
+            ctx.defer().await?;
+            playlist_delete(&client, &ctx).await
+        }
         _ => return Ok(()),
     };
 
@@ -124,6 +176,24 @@ pub async fn handle_component(client: Client, ctx: ComponentContext) -> Result<(
             ctx.defer_update().await?;
             delta_volume(&client, &ctx, -5).await?;
         }
+        MusicButtonOption::MUSIC_BUTTON_LOOP => {
+            ctx.defer_update().await?;
+            set_loop_mode(&client, &ctx, None).await?;
+        }
+        MusicButtonOption::MUSIC_BUTTON_REWIND => {
+            ctx.defer_update().await?;
+            seek(&client, &ctx, |position| {
+                position - SEEK_BUTTON_SECONDS * 1000
+            })
+            .await?;
+        }
+        MusicButtonOption::MUSIC_BUTTON_FORWARD => {
+            ctx.defer_update().await?;
+            seek(&client, &ctx, |position| {
+                position + SEEK_BUTTON_SECONDS * 1000
+            })
+            .await?;
+        }
         _ => return Ok(()),
     }
 
@@ -294,29 +364,44 @@ async fn play(client: &Client, ctx: &CommandContext) -> Result<Response> {
     };
 
     if queue.len() > 0 {
-        if let Some(mut state) = client.states.get_mut(&guild_id) {
-            state.value_mut().queue.extend(user.id, queue);
-        } else {
-            client.connect(guild_id, channel_id).await?;
-            let mut state_queue = MusicQueue::new();
-            state_queue.extend(user.id, queue);
-            client.states.insert(
-                guild_id,
-                PlayerState {
-                    skip_votes: HashSet::new(),
-                    queue: state_queue,
-                    now_playing_ui: None,
-                    queue_ui: None,
-                    queue_page: 0,
-                },
-            );
-            client.start_playing(guild_id, None).await?;
-        }
+        enqueue(client, guild_id, channel_id, user.id, queue).await?;
     }
     client.save_state(guild_id).await?;
     Ok(Response::direct().content(&response))
 }
 
+/// Adds tracks to the end of a user's queue, joining the voice channel and starting to play if
+/// nothing is currently playing.
+async fn enqueue(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    user_id: Id<UserMarker>,
+    tracks: Vec<Track>,
+) -> Result<()> {
+    if let Some(mut state) = client.states.get_mut(&guild_id) {
+        state.value_mut().queue.extend(user_id, tracks);
+        return Ok(());
+    }
+
+    client.connect(guild_id, channel_id).await?;
+    let mut state_queue = MusicQueue::new();
+    state_queue.extend(user_id, tracks);
+    client.states.insert(
+        guild_id,
+        PlayerState {
+            skip_votes: HashSet::new(),
+            queue: state_queue,
+            now_playing_ui: None,
+            queue_ui: None,
+            queue_page: 0,
+            loop_mode: LoopMode::LOOP_MODE_OFF,
+            seek_position: None,
+        },
+    );
+    client.start_playing(guild_id, None).await
+}
+
 async fn pause(
     client: &Client,
     ctx: &impl InteractionContext,
@@ -361,7 +446,10 @@ async fn skip(client: &Client, ctx: &impl InteractionContext) -> Result<Response
         .unwrap();
 
     let response = if votes >= required || requestor == user_id {
-        format!("Skipped `{}`", client.play_next(guild_id).await?.unwrap())
+        format!(
+            "Skipped `{}`",
+            client.play_next(guild_id, Advance::Skipped).await?.unwrap()
+        )
     } else {
         format!("Total votes: `{}/{}`.", votes, required)
     };
@@ -452,7 +540,7 @@ async fn shuffle(client: &Client, ctx: &CommandContext) -> Result<Response> {
 async fn forceskip(client: &Client, ctx: &CommandContext) -> Result<Response> {
     let guild_id = require_playing(client, ctx)?;
     require_dj(client, ctx).await?;
-    let response = if let Some(previous) = client.play_next(guild_id).await? {
+    let response = if let Some(previous) = client.play_next(guild_id, Advance::Skipped).await? {
         format!("Skipped `{}`.", previous)
     } else {
         "There is nothing in the queue right now.".to_owned()
@@ -476,7 +564,7 @@ async fn volume(client: &Client, ctx: &CommandContext) -> Result<Response> {
         // Update config
         let mut config = client.get_config(guild_id).await?;
         config.set_volume(vol as u32);
-        client.set_config(guild_id, config).await?;
+        client.set_config(guild_id, ctx.user().id, config).await?;
 
         format!("Set volume to `{}`.", vol)
     } else {
@@ -505,7 +593,7 @@ async fn delta_volume(
         volume
     };
     config.set_volume(volume as u32);
-    client.set_config(guild_id, config).await?;
+    client.set_config(guild_id, ctx.user().id, config).await?;
 
     Ok(Response::direct().content(&format!("Set volume to `{}`.", volume)))
 }
@@ -522,6 +610,290 @@ async fn shift_queue_page(
     Ok(Response::direct().content("Changed page."))
 }
 
+/// Parses a timestamp in the form of `[[hh:]mm:]ss`.
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let mut parts = timestamp.trim().split(':').rev();
+    let mut secs: u64 = parts.next()?.parse().ok()?;
+    if let Some(minutes) = parts.next() {
+        secs = secs.checked_add(minutes.parse::<u64>().ok()?.checked_mul(60)?)?;
+    }
+    if let Some(hours) = parts.next() {
+        secs = secs.checked_add(hours.parse::<u64>().ok()?.checked_mul(3600)?)?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Duration::from_secs(secs))
+}
+
+fn seek_seconds(ctx: &CommandContext) -> Result<i64> {
+    let seconds = ctx.get_int("seconds")?;
+    if seconds <= 0 {
+        bail!(InteractionError::InvalidArgument(
+            "The number of seconds must be positive.".to_owned()
+        ));
+    }
+    Ok(seconds)
+}
+
+async fn seek_command(client: &Client, ctx: &CommandContext) -> Result<Response> {
+    let timestamp = ctx.get_string("timestamp")?;
+    let position = parse_timestamp(timestamp).ok_or_else(|| {
+        InteractionError::InvalidArgument(format!(
+            "`{}` is not a valid timestamp. Use `hh:mm:ss`, `mm:ss` or seconds.",
+            timestamp
+        ))
+    })?;
+    let length = client
+        .currently_playing(ctx.guild_id()?)
+        .map(|track| track.info.length);
+    if let Some(length) = length.filter(|length| position > *length) {
+        bail!(InteractionError::InvalidArgument(format!(
+            "The current track is only {} long.",
+            format_duration(length)
+        )));
+    }
+    let position = i64::try_from(position.as_millis()).unwrap_or(i64::MAX);
+    seek(client, ctx, move |_| position).await
+}
+
+/// Seeks the currently playing track. `position` maps the current playback position to the new
+/// one, both in milliseconds. The new position is clamped to the length of the track.
+async fn seek(
+    client: &Client,
+    ctx: &impl InteractionContext,
+    position: impl FnOnce(i64) -> i64,
+) -> Result<Response> {
+    let guild_id = require_playing(client, ctx)?;
+    require_dj(client, ctx).await?;
+    let track = client.currently_playing(guild_id).unwrap();
+    if track.info.is_stream {
+        bail!(InteractionError::FailedPrecondition(
+            "Cannot seek in a live stream."
+        ));
+    }
+
+    let length = track.info.length.as_millis() as i64;
+    let position = position(client.position(guild_id).unwrap_or(0)).clamp(0, length);
+    get_player!(client, &guild_id).seek(position)?;
+    client.mutate_state(guild_id, |state| state.seek_position = Some(position));
+    client.save_state(guild_id).await?;
+    Ok(Response::direct().content(format!(
+        ":fast_forward: Seeked to `{}` in `{}`.",
+        format_duration(Duration::from_millis(position as u64)),
+        track.info
+    )))
+}
+
+async fn loop_command(client: &Client, ctx: &CommandContext) -> Result<Response> {
+    let mode = match ctx.get_string("mode").map(|mode| mode.as_str()) {
+        Ok("off") => Some(LoopMode::LOOP_MODE_OFF),
+        Ok("track") => Some(LoopMode::LOOP_MODE_TRACK),
+        Ok("user") => Some(LoopMode::LOOP_MODE_USER),
+        Ok("all") => Some(LoopMode::LOOP_MODE_ALL),
+        Ok(mode) => bail!(InteractionError::InvalidArgument(format!(
+            "`{}` is not a valid loop mode.",
+            mode
+        ))),
+        Err(_) => None,
+    };
+    set_loop_mode(client, ctx, mode).await
+}
+
+/// Sets the loop mode of the player. If no mode is provided, cycles to the next one.
+async fn set_loop_mode(
+    client: &Client,
+    ctx: &impl InteractionContext,
+    mode: Option<LoopMode>,
+) -> Result<Response> {
+    let guild_id = require_playing(client, ctx)?;
+    require_dj(client, ctx).await?;
+    let mode = client
+        .mutate_state(guild_id, |state| {
+            state.loop_mode = mode.unwrap_or(match state.loop_mode {
+                LoopMode::LOOP_MODE_OFF => LoopMode::LOOP_MODE_TRACK,
+                LoopMode::LOOP_MODE_TRACK => LoopMode::LOOP_MODE_USER,
+                LoopMode::LOOP_MODE_USER => LoopMode::LOOP_MODE_ALL,
+                LoopMode::LOOP_MODE_ALL => LoopMode::LOOP_MODE_OFF,
+            });
+            state.loop_mode
+        })
+        .unwrap();
+    client.save_state(guild_id).await?;
+    Ok(Response::direct().content(format!(
+        ":repeat: Set the loop mode to **{}**.",
+        player::loop_mode_name(mode)
+    )))
+}
+
+fn playlist_name(ctx: &CommandContext) -> Result<String> {
+    let name = ctx.get_string("name")?.trim();
+    if name.is_empty() || name.chars().count() > MAX_PLAYLIST_NAME_LENGTH {
+        bail!(InteractionError::InvalidArgument(format!(
+            "Playlist names must be between 1 and {} characters long.",
+            MAX_PLAYLIST_NAME_LENGTH
+        )));
+    }
+    Ok(name.to_owned())
+}
+
+/// Gets the owner of the playlists a command refers to: the server if the `server` flag is set,
+/// the user otherwise. Server playlists are shared, so only DJs can change them.
+async fn playlist_owner(
+    client: &Client,
+    ctx: &CommandContext,
+    modify: bool,
+) -> Result<PlaylistOwner> {
+    let guild_id = ctx.guild_id()?;
+    if !ctx.get_flag("server").unwrap_or(false) {
+        return Ok(PlaylistOwner::User(ctx.user().id));
+    }
+    if modify {
+        let config = client.get_config(guild_id).await?;
+        let dj = ctx
+            .member()
+            .map(|member| is_dj(&config, &member.roles))
+            .unwrap_or(false);
+        if !dj {
+            bail!(InteractionError::FailedPrecondition(
+                "User must be a DJ to change the server's playlists."
+            ));
+        }
+    }
+    Ok(PlaylistOwner::Guild(guild_id))
+}
+
+async fn playlist_save(client: &Client, ctx: &CommandContext) -> Result<Response> {
+    let guild_id = ctx.guild_id()?;
+    let user_id = ctx.user().id;
+    let name = playlist_name(ctx)?;
+    let owner = playlist_owner(client, ctx, true).await?;
+
+    // Server playlists save the whole queue, user playlists only the user's own tracks.
+    let tracks: Vec<Track> = client
+        .get_queue(guild_id, |queue| match owner {
+            PlaylistOwner::Guild(_) => queue.iter().map(|item| item.value.clone()).collect(),
+            PlaylistOwner::User(_) => queue.iter_with_key(user_id).into_iter().cloned().collect(),
+        })
+        .unwrap_or_default();
+    if tracks.is_empty() {
+        bail!(InteractionError::FailedPrecondition(
+            "There are no tracks in the queue to save."
+        ));
+    } else if tracks.len() > MAX_PLAYLIST_TRACKS {
+        bail!(InteractionError::InvalidArgument(format!(
+            "Playlists cannot have more than {} tracks.",
+            MAX_PLAYLIST_TRACKS
+        )));
+    }
+
+    let existing = Playlist::fetch(owner, &name)
+        .fetch_optional(&client.sql)
+        .await?;
+    if existing.is_none() {
+        let (count,) = Playlist::count(owner).fetch_one(&client.sql).await?;
+        if count >= MAX_PLAYLISTS {
+            bail!(InteractionError::FailedPrecondition(
+                "Too many playlists have been saved. Delete one before saving another."
+            ));
+        }
+    }
+
+    let count = tracks.len();
+    let mut playlist = PlaylistProto::new();
+    for track in tracks {
+        let mut track = TrackProto::from(track);
+        // The requestor is replaced with whoever loads the playlist.
+        track.clear_requestor();
+        playlist.mut_tracks().push(track);
+    }
+    Playlist::save(owner, &name, playlist)
+        .execute(&client.sql)
+        .await?;
+    Ok(Response::direct().content(format!(
+        ":floppy_disk: Saved **{}** tracks to the playlist `{}`.",
+        count, name
+    )))
+}
+
+async fn playlist_load(client: &Client, ctx: &CommandContext) -> Result<Response> {
+    let (guild_id, channel_id) = require_in_voice_channel(client, ctx).await?;
+    let user = ctx.user();
+    let name = playlist_name(ctx)?;
+    let owner = playlist_owner(client, ctx, false).await?;
+    let playlist = Playlist::fetch(owner, &name)
+        .fetch_optional(&client.sql)
+        .await?
+        .ok_or_else(|| {
+            InteractionError::InvalidArgument(format!("There is no playlist named `{}`.", name))
+        })?;
+
+    let requestor = CachedUserProto::from(user.clone());
+    let tracks: Vec<Track> = playlist
+        .tracks()
+        .get_tracks()
+        .iter()
+        .cloned()
+        .map(|mut track| {
+            *track.mut_requestor() = requestor.clone();
+            Track::from(track)
+        })
+        .collect();
+    let count = tracks.len();
+    let duration = format_duration(tracks.iter().map(|t| t.info.length).sum());
+    if count > 0 {
+        enqueue(client, guild_id, channel_id, user.id, tracks).await?;
+    }
+    client.save_state(guild_id).await?;
+    Ok(Response::direct().content(format!(
+        ":notes: Added **{}** tracks ({}) from the playlist `{}` to the music queue.",
+        count, duration, name
+    )))
+}
+
+async fn playlist_list(client: &Client, ctx: &CommandContext) -> Result<Response> {
+    let owner = playlist_owner(client, ctx, false).await?;
+    let playlists = Playlist::fetch_all(owner).fetch_all(&client.sql).await?;
+    if playlists.is_empty() {
+        return Ok(Response::direct().content("There are no saved playlists."));
+    }
+
+    let mut lines = Vec::new();
+    let mut length = 0;
+    for playlist in playlists.iter() {
+        let tracks = playlist.tracks().get_tracks();
+        let duration = tracks.iter().map(|t| t.get_length()).sum();
+        let line = format!(
+            "`{}`: {} tracks ({})",
+            playlist.name,
+            tracks.len(),
+            format_duration(Duration::from_millis(duration))
+        );
+        length += line.len() + 1;
+        if length > MAX_RESPONSE_LENGTH {
+            break;
+        }
+        lines.push(line);
+    }
+    if lines.len() < playlists.len() {
+        lines.push(format!("...and {} more.", playlists.len() - lines.len()));
+    }
+    Ok(Response::direct().content(lines.join("\n")))
+}
+
+async fn playlist_delete(client: &Client, ctx: &CommandContext) -> Result<Response> {
+    let name = playlist_name(ctx)?;
+    let owner = playlist_owner(client, ctx, true).await?;
+    let result = Playlist::delete(owner, &name).execute(&client.sql).await?;
+    if result.rows_affected() == 0 {
+        bail!(InteractionError::InvalidArgument(format!(
+            "There is no playlist named `{}`.",
+            name
+        )));
+    }
+    Ok(Response::direct().content(format!("Deleted the playlist `{}`.", name)))
+}
+
 async fn queue(client: &Client, ctx: CommandContext) -> Result<()> {
     let guild_id = ctx.guild_id()?;
     let ui = EmbedUI::<QueueUI>::create(client.clone(), ctx).await?;
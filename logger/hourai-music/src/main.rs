@@ -7,7 +7,7 @@ mod queue;
 mod track;
 
 use crate::{
-    player::PlayerState,
+    player::{Advance, PlayerState},
     prelude::*,
     queue::MusicQueue,
     track::{Track, TrackInfo},
@@ -26,7 +26,10 @@ use hourai::{
         http::interaction::*,
         id::{marker::*, Id},
     },
-    proto::{guild_configs::MusicConfig, music_bot::MusicStateProto},
+    proto::{
+        guild_configs::MusicConfig,
+        music_bot::{LoopMode, MusicStateProto},
+    },
 };
 use hourai_redis::*;
 use http::Uri;
@@ -64,7 +67,8 @@ async fn main() {
     init::init(&config);
 
     let http_client = Arc::new(init::http_client(&config));
-    let redis = hourai_redis::init(&config).await;
+    let sql = hourai_sql::init(&config).await;
+    let redis = hourai_redis::init(&config, sql.clone()).await;
     let sessions = redis.resume_states().get_sessions(RESUME_KEY).await;
     let (gateway, mut events) = init::cluster(&config, BOT_INTENTS)
         .http_client(http_client.clone())
@@ -93,6 +97,7 @@ async fn main() {
         states: Arc::new(DashMap::new()),
         hyper: HyperClient::new(),
         redis: redis.clone(),
+        sql,
     };
 
     // Start the lavalink node connections.
@@ -140,6 +145,7 @@ pub struct Client {
     pub lavalink: Arc<twilight_lavalink::Lavalink>,
     pub states: Arc<DashMap<Id<GuildMarker>, PlayerState>>,
     pub redis: RedisClient,
+    pub sql: hourai_sql::SqlPool,
 }
 
 impl Client {
@@ -297,7 +303,10 @@ impl Client {
                 Ok(())
             }
             IncomingEvent::TrackEnd(evt) => self.on_track_end(evt).await,
-            IncomingEvent::PlayerUpdate(evt) => self.save_state(evt.guild_id).await,
+            IncomingEvent::PlayerUpdate(evt) => {
+                self.mutate_state(evt.guild_id, |state| state.seek_position = None);
+                self.save_state(evt.guild_id).await
+            }
             _ => Ok(()),
         };
 
@@ -318,10 +327,10 @@ impl Client {
         );
         match evt.reason.as_str() {
             "FINISHED" => {
-                self.play_next(evt.guild_id).await?;
+                self.play_next(evt.guild_id, Advance::Finished).await?;
             }
             "LOAD_FAILED" => {
-                self.play_next(evt.guild_id).await?;
+                self.play_next(evt.guild_id, Advance::Failed).await?;
             }
             _ => {}
         }
@@ -334,9 +343,15 @@ impl Client {
         Ok(config)
     }
 
-    /// Sets the music config for the sever.
-    pub async fn set_config(&self, guild_id: Id<GuildMarker>, config: MusicConfig) -> Result<()> {
-        self.redis.guild(guild_id).configs().set(config).await?;
+    /// Sets the music config for the sever and records the change in its config history.
+    pub async fn set_config(
+        &self,
+        guild_id: Id<GuildMarker>,
+        author_id: Id<UserMarker>,
+        config: MusicConfig,
+    ) -> Result<()> {
+        let storage = hourai_storage::Storage::new(self.sql.clone(), self.redis.clone());
+        hourai_storage::config::set_config(&storage, guild_id, author_id, config).await?;
         Ok(())
     }
 
@@ -347,12 +362,7 @@ impl Client {
             .map(|kv| kv.value().save_to_proto());
         let mut queue = self.redis.guild(guild_id).music_queue();
         if let Some(mut state) = state {
-            let position = self
-                .lavalink
-                .players()
-                .get(&guild_id)
-                .map(|kv| kv.position());
-            if let Some(position) = position {
+            if let Some(position) = self.position(guild_id) {
                 state.set_position(position);
             }
             queue.save(state).await?;
@@ -394,6 +404,24 @@ impl Client {
         self.states.get(&guild_id).map(|kv| kv.value().queue_page)
     }
 
+    /// Gets the playback position of the player in a given guild in milliseconds, including any
+    /// seeks that Lavalink has not reported back yet.
+    /// If not playing, return None.
+    pub fn position(&self, guild_id: Id<GuildMarker>) -> Option<i64> {
+        let position = self.lavalink.players().get(&guild_id)?.position();
+        let seek_position = self
+            .states
+            .get(&guild_id)
+            .and_then(|kv| kv.value().seek_position);
+        Some(seek_position.unwrap_or(position))
+    }
+
+    /// Gets the loop mode of the player in a given guild.
+    /// If not playing, return None.
+    pub fn loop_mode(&self, guild_id: Id<GuildMarker>) -> Option<LoopMode> {
+        self.states.get(&guild_id).map(|kv| kv.value().loop_mode)
+    }
+
     /// Gets which voice channel the bot is currently connected to in
     /// a guild.
     pub fn get_channel(&self, guild_id: Id<GuildMarker>) -> Option<Id<ChannelMarker>> {
@@ -473,14 +501,16 @@ impl Client {
         Ok(())
     }
 
-    /// Plays the next item in the queue.
+    /// Plays the next item in the queue, respecting the player's loop mode.
     /// Panics if a player does not exist.
-    pub async fn play_next(&self, guild_id: Id<GuildMarker>) -> Result<Option<TrackInfo>> {
+    pub async fn play_next(
+        &self,
+        guild_id: Id<GuildMarker>,
+        reason: Advance,
+    ) -> Result<Option<TrackInfo>> {
         let prev = {
             if let Some(mut kv) = self.states.get_mut(&guild_id) {
-                let state = kv.value_mut();
-                state.skip_votes.clear();
-                state.queue.pop().map(|kv| kv.value.info)
+                kv.value_mut().advance(reason).map(|track| track.info)
             } else {
                 return Ok(None);
             }
@@ -14,6 +14,31 @@ pub struct PlayerState {
     pub queue_ui: Option<interaction_ui::MessageUI>,
 
     pub queue_page: i64,
+    pub loop_mode: LoopMode,
+    /// The position the player last seeked to, in milliseconds. Cleared once Lavalink reports
+    /// the player's position again.
+    pub seek_position: Option<i64>,
+}
+
+/// The reason the player is moving on from the currently playing track.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Advance {
+    /// The track finished playing normally.
+    Finished,
+    /// The track was skipped by a user.
+    Skipped,
+    /// The track failed to load and should be dropped from the queue.
+    Failed,
+}
+
+/// Gets a human readable name for a loop mode.
+pub fn loop_mode_name(mode: LoopMode) -> &'static str {
+    match mode {
+        LoopMode::LOOP_MODE_OFF => "Off",
+        LoopMode::LOOP_MODE_TRACK => "Track",
+        LoopMode::LOOP_MODE_USER => "User Queue",
+        LoopMode::LOOP_MODE_ALL => "All",
+    }
 }
 
 impl PlayerState {
@@ -24,6 +49,8 @@ impl PlayerState {
             now_playing_ui: None,
             queue_ui: None,
             queue_page: 0,
+            loop_mode: LoopMode::LOOP_MODE_OFF,
+            seek_position: None,
         }
     }
 
@@ -35,17 +62,42 @@ impl PlayerState {
         self.queue.peek().is_some()
     }
 
+    /// Moves the queue on from the currently playing track according to the loop mode. Returns
+    /// the track that was moved on from, if any.
+    pub fn advance(&mut self, reason: Advance) -> Option<Track> {
+        self.skip_votes.clear();
+        self.seek_position = None;
+        match (self.loop_mode, reason) {
+            (_, Advance::Failed)
+            | (LoopMode::LOOP_MODE_OFF, _)
+            | (LoopMode::LOOP_MODE_TRACK, Advance::Skipped) => {
+                self.queue.pop().map(|item| item.value)
+            }
+            (LoopMode::LOOP_MODE_TRACK, Advance::Finished) => {
+                self.queue.peek().map(|item| item.value.clone())
+            }
+            (LoopMode::LOOP_MODE_USER, _) => self.queue.cycle().map(|item| item.value.clone()),
+            (LoopMode::LOOP_MODE_ALL, _) => {
+                let item = self.queue.pop()?;
+                self.queue.push(item.key, item.value.clone());
+                Some(item.value)
+            }
+        }
+    }
+
     pub fn save_to_proto(&self) -> MusicStateProto {
         let mut proto = MusicStateProto::new();
         *proto.mut_queue() = MusicQueueProto::from(&self.queue);
         proto
             .skip_votes
             .extend(self.skip_votes.iter().map(|id| id.get()));
+        proto.set_loop_mode(self.loop_mode);
         proto
     }
 
     pub fn load_from_proto(&mut self, mut state: MusicStateProto) {
         self.queue = state.take_queue().into();
+        self.loop_mode = state.get_loop_mode();
         self.skip_votes = state.skip_votes.into_iter().map(Id::new).collect();
     }
 }
@@ -81,6 +81,17 @@ where
         Some(QueueItem { key, value })
     }
 
+    /// Moves the first item in the queue to the back of its key's queue without moving on to the
+    /// next key. Returns the moved item. This is a O(1) operation.
+    pub fn cycle(&mut self) -> Option<QueueItem<K, &V>> {
+        let (key, bucket) = self.0.get_mut(0)?;
+        bucket.rotate_left(1);
+        Some(QueueItem {
+            key: *key,
+            value: bucket.back()?,
+        })
+    }
+
     /// Gets the total number of items in the queue. If there are n keys and k values in the queue
     /// for a given key, this is a O(n) operation.
     pub fn len(&self) -> usize {
@@ -260,6 +271,32 @@ mod test {
         assert_eq!(queue.pop(), None);
     }
 
+    #[test]
+    fn test_queue_cycle() {
+        let mut queue: MusicQueue<u64, u64> = MusicQueue::new();
+        assert_eq!(queue.cycle(), None);
+        queue.extend(20, vec![20, 40]);
+        queue.push(10, 10);
+        assert_eq!(
+            queue.cycle(),
+            Some(QueueItem {
+                key: 20,
+                value: &20
+            })
+        );
+        assert_eq!(
+            queue.peek(),
+            Some(QueueItem {
+                key: 20,
+                value: &40
+            })
+        );
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.pop(), Some(QueueItem { key: 20, value: 40 }));
+        assert_eq!(queue.pop(), Some(QueueItem { key: 10, value: 10 }));
+        assert_eq!(queue.pop(), Some(QueueItem { key: 20, value: 20 }));
+    }
+
     #[test]
     fn test_queue_extend() {
         let mut queue: MusicQueue<u64, u64> = MusicQueue::new();
@@ -30,7 +30,7 @@ async fn main() -> std::io::Result<()> {
 
     let port = config.web.port;
     let sql = hourai_sql::init(&config).await;
-    let redis = hourai_redis::init(&config).await;
+    let redis = hourai_redis::init(&config, sql.clone()).await;
 
     HttpServer::new(move || {
         App::new()
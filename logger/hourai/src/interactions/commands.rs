@@ -8,6 +8,7 @@ use crate::{
                 CommandData, CommandDataOption, CommandOptionValue, InteractionMember,
             },
         },
+        channel::Attachment,
         guild::{PartialMember, Permissions},
         id::{
             marker::{
@@ -103,6 +104,15 @@ impl CommandContext {
             })
     }
 
+    /// Gets all of the Role options with a given option name.
+    pub fn all_roles(&self, name: &'static str) -> impl Iterator<Item = Id<RoleMarker>> + '_ {
+        self.all_options_named(name)
+            .filter_map(|opt| match opt.value {
+                CommandOptionValue::Role(role) => Some(role),
+                _ => None,
+            })
+    }
+
     /// Gets all of the String options with a given option name.
     pub fn all_strings(&self, name: &'static str) -> impl Iterator<Item = &str> + '_ {
         self.all_options_named(name)
@@ -167,6 +177,21 @@ impl CommandContext {
             .ok_or(InteractionError::MissingArgument(name))
     }
 
+    /// Attempts to find the first argument with a given name that is of type Attachment. If no
+    /// such argument is found, return None.
+    pub fn get_attachment(&self, name: &'static str) -> InteractionResult<&Attachment> {
+        self.option_named(name)
+            .and_then(|option| match option.value {
+                CommandOptionValue::Attachment(ref id) => self
+                    .data()
+                    .resolved
+                    .as_ref()
+                    .and_then(|r| r.attachments.get(id)),
+                _ => None,
+            })
+            .ok_or(InteractionError::MissingArgument(name))
+    }
+
     /// Checks if a boolean flag is set to true or not. If no flag with the name was found, it
     /// returs None.
     pub fn get_flag(&self, name: &'static str) -> Option<bool> {
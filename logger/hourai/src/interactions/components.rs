@@ -52,6 +52,11 @@ impl ComponentContext {
         let decoded = base64::decode(&self.data().custom_id)?;
         Ok(MessageComponentProto::parse_from_bytes(&decoded)?)
     }
+
+    /// Gets the values chosen in a select menu. Empty for buttons.
+    pub fn values(&self) -> &[String] {
+        &self.data().values
+    }
 }
 
 impl InteractionContext for ComponentContext {
@@ -9,7 +9,7 @@ use crate::{
     models::{
         channel::message::{embed::Embed, MessageFlags, component::Component},
         guild::{PartialMember, Permissions},
-        http::interaction::*,
+        http::{attachment::Attachment, interaction::*},
         id::{
             marker::{ApplicationMarker, ChannelMarker, GuildMarker, InteractionMarker},
             Id,
@@ -83,6 +83,15 @@ impl Response {
         self
     }
 
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        if let Some(attachments) = self.0.attachments.as_mut() {
+            attachments.push(attachment)
+        } else {
+            self.0.attachments = Some(vec![attachment]);
+        }
+        self
+    }
+
     pub fn components(mut self, components: &[Component]) -> Self {
         if let Some(ref mut comps) = self.0.components {
             comps.extend(components.iter().cloned());
@@ -152,13 +161,16 @@ pub trait InteractionContext {
         data: impl Into<InteractionResponseData> + Send + 'static,
     ) -> anyhow::Result<()> {
         let data = data.into();
-        self.http()
-            .interaction(self.application_id())
+        let client = self.http().interaction(self.application_id());
+        let mut request = client
             .update_response(self.token())
             .content(data.content.as_deref())?
             .embeds(data.embeds.as_deref())?
-            .components(data.components.as_deref())?
-            .await?;
+            .components(data.components.as_deref())?;
+        if let Some(attachments) = data.attachments.as_deref() {
+            request = request.attachments(attachments)?;
+        }
+        request.await?;
         Ok(())
     }
 
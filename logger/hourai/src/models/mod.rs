@@ -31,6 +31,7 @@ bitflags::bitflags! {
         const DJ = 1;
         const MODERATOR = 1 << 2;
         const RESTORABLE = 1 << 3;
+        const SELF_SERVE = 1 << 4;
     }
 }
 
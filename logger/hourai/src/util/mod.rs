@@ -1,2 +1,3 @@
 pub mod mentions;
+pub mod placeholders;
 pub mod whois;
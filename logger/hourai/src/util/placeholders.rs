@@ -0,0 +1,44 @@
+/// The values substituted into a message template. Placeholders are written as `{name}`. Unknown
+/// placeholders are left as is.
+#[derive(Default)]
+pub struct Placeholders(Vec<(&'static str, String)>);
+
+impl Placeholders {
+    pub fn set(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.0.push((name, value.into()));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Renders a template in a single pass, so placeholders within the substituted values are
+    /// not expanded.
+    pub fn render(&self, template: &str) -> String {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            output.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest
+                .find('}')
+                .and_then(|end| self.get(&rest[1..end]).map(|value| (value, end)));
+            match value {
+                Some((value, end)) => {
+                    output.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    output.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        output.push_str(rest);
+        output
+    }
+}
@@ -15,6 +15,64 @@ use std::{collections::HashSet, sync::Arc};
 
 const SECONDS_IN_DAY: u32 = 24 * 60 * 60;
 
+fn describe_status(status: StatusType) -> &'static str {
+    match status {
+        StatusType::APPLY => "Apply",
+        StatusType::UNAPPLY => "Remove",
+        StatusType::TOGGLE => "Toggle",
+    }
+}
+
+/// Creates a short human readable description of an action.
+pub fn describe_action(action: &Action) -> String {
+    let description = match &action.details {
+        Some(Action_oneof_details::kick(_)) => "Kick".to_owned(),
+        Some(Action_oneof_details::ban(ban)) => match ban.get_field_type() {
+            BanMember_Type::BAN => "Ban".to_owned(),
+            BanMember_Type::UNBAN => "Unban".to_owned(),
+            BanMember_Type::SOFTBAN => "Softban".to_owned(),
+        },
+        Some(Action_oneof_details::escalate(escalate)) => {
+            format!("Escalate by {}", escalate.get_amount())
+        }
+        Some(Action_oneof_details::mute(mute)) => {
+            format!("{} server mute", describe_status(mute.get_field_type()))
+        }
+        Some(Action_oneof_details::deafen(deafen)) => {
+            format!("{} server deafen", describe_status(deafen.get_field_type()))
+        }
+        Some(Action_oneof_details::change_role(change_role)) => {
+            let roles: Vec<_> = change_role
+                .get_role_ids()
+                .iter()
+                .map(|id| format!("<@&{}>", id))
+                .collect();
+            format!(
+                "{} roles: {}",
+                describe_status(change_role.get_field_type()),
+                roles.join(", ")
+            )
+        }
+        Some(Action_oneof_details::direct_message(_)) => "Send a direct message".to_owned(),
+        Some(Action_oneof_details::send_message(message)) => {
+            format!("Send a message in <#{}>", message.get_channel_id())
+        }
+        Some(Action_oneof_details::delete_messages(_)) => "Delete messages".to_owned(),
+        None => "Unknown action".to_owned(),
+    };
+
+    if action.has_duration() {
+        let duration = std::time::Duration::from_secs(action.get_duration());
+        format!(
+            "{} for {}",
+            description,
+            humantime::format_duration(duration)
+        )
+    } else {
+        description
+    }
+}
+
 #[derive(Clone)]
 pub struct ActionExecutor {
     current_user: User,
@@ -91,22 +149,30 @@ impl ActionExecutor {
         if action.has_duration() {
             let timestamp = Utc::now() + Duration::seconds(action.get_duration() as i64);
             let mut undo = action.clone();
-            Self::invert_action(&mut undo);
-            undo.clear_duration();
-            PendingAction::schedule(undo, timestamp)
-                .execute(self.storage().sql())
-                .await?;
+            if Self::invert_action(&mut undo) {
+                undo.clear_duration();
+                undo.set_reason(format!(
+                    "Undoing \"{}\" (Original reason: {})",
+                    describe_action(action),
+                    action.get_reason()
+                ));
+                PendingAction::schedule(undo, timestamp)
+                    .execute(self.storage().sql())
+                    .await?;
+            }
         }
         Ok(())
     }
 
-    fn invert_action(action: &mut Action) {
+    /// Inverts the action in place. Returns false if the action does not need to be undone.
+    fn invert_action(action: &mut Action) -> bool {
         match &mut action.details {
             Some(Action_oneof_details::ban(ref mut info)) => {
                 info.set_field_type(match info.get_field_type() {
                     BanMember_Type::BAN => BanMember_Type::UNBAN,
                     BanMember_Type::UNBAN => BanMember_Type::BAN,
-                    BanMember_Type::SOFTBAN => panic!("Cannot invert a softban"),
+                    // Softbans immediately undo themselves.
+                    BanMember_Type::SOFTBAN => return false,
                 });
             }
             Some(Action_oneof_details::escalate(ref mut info)) => {
@@ -126,6 +192,7 @@ impl ActionExecutor {
             }
             None => panic!("Cannot invert action without a specified type"),
         }
+        true
     }
 
     fn invert_status(status: StatusType) -> StatusType {
@@ -156,11 +223,37 @@ impl ActionExecutor {
                 .delete_message_seconds(info.get_delete_message_days() as u32 * SECONDS_IN_DAY)?
                 .await?;
         }
-        if info.get_field_type() != BanMember_Type::BAN {
-            self.http
-                .delete_ban(guild_id, user_id)
-                .reason(action.get_reason())?
-                .await?;
+        match info.get_field_type() {
+            BanMember_Type::BAN => {}
+            BanMember_Type::UNBAN => {
+                self.http
+                    .delete_ban(guild_id, user_id)
+                    .reason(action.get_reason())?
+                    .await?;
+            }
+            BanMember_Type::SOFTBAN => {
+                let result = self
+                    .http
+                    .delete_ban(guild_id, user_id)
+                    .reason(action.get_reason())?
+                    .await;
+                // The ban has already gone through. Hand the unban off to the pending action
+                // queue to be retried instead of leaving the user banned.
+                if let Err(err) = result {
+                    tracing::error!(
+                        "Failed to unban user {} in guild {} after softban, retrying later: {}",
+                        user_id,
+                        guild_id,
+                        err
+                    );
+                    let mut unban = action.clone();
+                    unban.clear_duration();
+                    unban.mut_ban().set_field_type(BanMember_Type::UNBAN);
+                    PendingAction::schedule(unban, Utc::now())
+                        .execute(self.storage().sql())
+                        .await?;
+                }
+            }
         }
         Ok(())
     }
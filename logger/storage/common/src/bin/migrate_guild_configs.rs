@@ -0,0 +1,29 @@
+//! One-time migration that copies every guild config stored in Redis into the database. Configs
+//! already stored in the database are left untouched, so this is safe to run more than once.
+
+use hourai::{config, init};
+use hourai_storage::Storage;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = config::load_config(config::get_config_path().as_ref());
+    init::init(&config);
+
+    let storage = Storage::init(&config).await;
+    let mut redis = storage.redis().clone();
+    let guild_ids = redis.guilds_with_configs().await?;
+    tracing::info!("Migrating configs for {} guilds", guild_ids.len());
+
+    let mut total = 0;
+    for guild_id in guild_ids {
+        match redis.guild(guild_id).configs().persist().await {
+            Ok(count) => total += count,
+            Err(err) => {
+                tracing::error!("Failed to migrate configs for guild {}: {}", guild_id, err)
+            }
+        }
+    }
+
+    tracing::info!("Copied {} guild configs into the database", total);
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use crate::Storage;
+use anyhow::Result;
+use hourai::{
+    models::id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
+    proto::guild_configs::GuildConfig,
+};
+use hourai_redis::{config_sections, CachedGuildConfig};
+use hourai_sql::GuildConfigRevision;
+use protobuf::Message;
+
+/// Sets one of a guild's configs and records the change in the guild's config history. Returns
+/// the version of the newly written config.
+pub async fn set_config<T: Message + CachedGuildConfig>(
+    storage: &Storage,
+    guild_id: Id<GuildMarker>,
+    author_id: Id<UserMarker>,
+    config: T,
+) -> Result<i32> {
+    let (version,) =
+        GuildConfigRevision::insert(guild_id, T::SUBKEY, author_id, config.write_to_bytes()?)
+            .fetch_one(storage.sql())
+            .await?;
+    storage
+        .redis()
+        .guild(guild_id)
+        .configs()
+        .set(config)
+        .await?;
+    Ok(version)
+}
+
+/// Sets all of the configs that are set in the provided proto, recording each of them in the
+/// guild's config history. The history is only committed if all of the configs were written.
+pub async fn set_all_configs(
+    storage: &Storage,
+    guild_id: Id<GuildMarker>,
+    author_id: Id<UserMarker>,
+    config: GuildConfig,
+) -> Result<()> {
+    let revisions = config_sections(&config)?;
+    let mut txn = storage.sql().begin().await?;
+    for (subkey, data) in revisions {
+        GuildConfigRevision::insert(guild_id, subkey, author_id, data)
+            .fetch_one(&mut txn)
+            .await?;
+    }
+    storage
+        .redis()
+        .guild(guild_id)
+        .configs()
+        .set_all(config)
+        .await?;
+    txn.commit().await?;
+    Ok(())
+}
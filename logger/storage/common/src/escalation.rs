@@ -15,6 +15,7 @@ use hourai::{
 use hourai::{
     models::user::User,
     proto::action::{Action, ActionSet},
+    util::placeholders::Placeholders,
 };
 use hourai_sql::{EscalationEntry, Executor, PendingDeescalation};
 use std::{
@@ -23,6 +24,7 @@ use std::{
     sync::Arc,
 };
 use thiserror::Error;
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder, EmbedFooterBuilder};
 
 pub struct Escalation {
     pub current_level: i64,
@@ -272,7 +274,12 @@ impl EscalationHistory {
         Ok(escalation)
     }
 
-    fn get_rung(&self, level: i64) -> Option<&EscalationLadderRung> {
+    /// Gets the ladder rung for a given escalation level. Levels past the end of the ladder
+    /// return the last rung. Returns None for negative levels.
+    ///
+    /// # Panics
+    /// Panics if the guild does not have an escalation ladder configured.
+    pub fn get_rung(&self, level: i64) -> Option<&EscalationLadderRung> {
         if level < 0 {
             None
         } else {
@@ -310,9 +317,17 @@ impl EscalationHistory {
             .configs()
             .get()
             .await?;
-        let modlog_id = Id::new(config.get_modlog_channel_id());
-        let arrow = if diff > 0 { "up" } else { "down" };
-        let esc = if diff > 0 { "escalated" } else { "deescalated" };
+        let log_config = config.get_escalations();
+        let channel_id = if log_config.get_output_channel_id() != 0 {
+            log_config.get_output_channel_id()
+        } else {
+            config.get_modlog_channel_id()
+        };
+        let channel_id = match Id::new_checked(channel_id) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
         let reasons: HashSet<&str> = escalation
             .entry
             .action
@@ -322,21 +337,94 @@ impl EscalationHistory {
             .map(|a| a.get_reason())
             .collect();
         let reasons = reasons.into_iter().collect::<Vec<_>>().join("; ");
-        let msg = format!(
-            ":arrow_{}: **<@{}> {} <@{}>**\nReason: {}\nAction: {}\nExpiration: {}",
-            arrow,
-            escalation.entry.authorizer_id,
-            esc,
-            escalation.entry.subject_id,
-            reasons,
-            escalation.entry.display_name,
-            escalation.expiration()
-        );
-        self.http()
-            .create_message(modlog_id)
-            .content(&msg)?
-            .await?;
+        // The newly created entry has not been added to the fetched entries.
+        let history_count = self.entries.len() + 1;
+
+        let request = self.http().create_message(channel_id);
+        if log_config.get_rich_embed() {
+            let embed = escalation_embed(escalation, diff, &reasons, history_count).build();
+            request.embeds(&[embed])?.await?;
+        } else {
+            let template = if log_config.has_template() {
+                log_config.get_template()
+            } else {
+                DEFAULT_TEMPLATE
+            };
+            let msg = render_template(template, escalation, diff, &reasons, history_count);
+            request.content(&msg)?.await?;
+        }
 
         Ok(())
     }
 }
+
+const DEFAULT_TEMPLATE: &str = ":arrow_{direction}: **{authorizer} {escalated} {user}**\n\
+                                Reason: {reason}\nAction: {action}\nExpiration: {expiration}";
+
+fn rung_name(rung: Option<&EscalationLadderRung>) -> &str {
+    rung.map(|rung| rung.get_display_name()).unwrap_or("None")
+}
+
+fn render_template(
+    template: &str,
+    escalation: &Escalation,
+    diff: i64,
+    reasons: &str,
+    history_count: usize,
+) -> String {
+    let (direction, escalated) = if diff > 0 {
+        ("up", "escalated")
+    } else {
+        ("down", "deescalated")
+    };
+    Placeholders::default()
+        .set("direction", direction)
+        .set("escalated", escalated)
+        .set(
+            "authorizer",
+            format!("<@{}>", escalation.entry.authorizer_id),
+        )
+        .set("user", format!("<@{}>", escalation.entry.subject_id))
+        .set("action", escalation.entry.display_name.clone())
+        .set("level", escalation.current_level.to_string())
+        .set("next_action", rung_name(escalation.next_rung.as_ref()))
+        .set("expiration", escalation.expiration())
+        .set("history_count", history_count.to_string())
+        .set("reason", reasons)
+        .render(template)
+}
+
+fn escalation_embed(
+    escalation: &Escalation,
+    diff: i64,
+    reasons: &str,
+    history_count: usize,
+) -> EmbedBuilder {
+    let (title, color) = if diff > 0 {
+        ("User Escalated", 0xE74C3C)
+    } else {
+        ("User Deescalated", 0x2ECC71)
+    };
+    EmbedBuilder::new()
+        .title(title)
+        .color(color)
+        .description(format!(
+            "<@{}> by <@{}>",
+            escalation.entry.subject_id, escalation.entry.authorizer_id
+        ))
+        .field(EmbedFieldBuilder::new("Reason", reasons))
+        .field(EmbedFieldBuilder::new("Action", &escalation.entry.display_name).inline())
+        .field(
+            EmbedFieldBuilder::new("Current Rung", rung_name(escalation.current_rung.as_ref()))
+                .inline(),
+        )
+        .field(
+            EmbedFieldBuilder::new("Next Rung", rung_name(escalation.next_rung.as_ref())).inline(),
+        )
+        .field(EmbedFieldBuilder::new("Expiration", escalation.expiration()).inline())
+        .field(EmbedFieldBuilder::new("History", format!("{} entries", history_count)).inline())
+        .footer(EmbedFooterBuilder::new(format!(
+            "User ID: {}",
+            escalation.entry.subject_id
+        )))
+}
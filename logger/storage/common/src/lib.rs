@@ -2,6 +2,7 @@
 extern crate delegate;
 
 pub mod actions;
+pub mod config;
 pub mod escalation;
 mod storage;
 
@@ -16,32 +17,53 @@ use hourai::{
             marker::{GuildMarker, RoleMarker},
             Id,
         },
+        RoleFlags,
     },
-    proto::cache::CachedRoleProto,
+    proto::{cache::CachedRoleProto, guild_configs::RoleConfig},
 };
 use hourai_redis::RedisClient;
 use hourai_sql::{Member, SqlPool};
 use rand::Rng;
 use std::collections::HashSet;
 
-pub fn is_moderator_role(role: &CachedRoleProto) -> bool {
-    let name = role.get_name().to_lowercase();
+fn role_flags(config: &RoleConfig, role_id: u64) -> RoleFlags {
+    config
+        .get_settings()
+        .get(&role_id)
+        .map(|settings| RoleFlags::from_bits_truncate(settings.get_flags()))
+        .unwrap_or_else(RoleFlags::empty)
+}
+
+/// Checks if a role is a moderator role. Roles with the Administrator permission or the Moderator
+/// flag always are. If no role in the server has the Moderator flag, roles with names starting
+/// with "mod" or "admin" are also treated as moderator roles.
+pub fn is_moderator_role(role: &CachedRoleProto, config: &RoleConfig) -> bool {
     let perms = Permissions::from_bits_truncate(role.get_permissions());
-    perms.contains(Permissions::ADMINISTRATOR)
-        || name.starts_with("mod")
-        || name.starts_with("admin")
+    if perms.contains(Permissions::ADMINISTRATOR)
+        || role_flags(config, role.get_role_id()).contains(RoleFlags::MODERATOR)
+    {
+        return true;
+    }
+
+    let has_flagged_roles = config
+        .get_settings()
+        .keys()
+        .any(|role_id| role_flags(config, *role_id).contains(RoleFlags::MODERATOR));
+    let name = role.get_name().to_lowercase();
+    !has_flagged_roles && (name.starts_with("mod") || name.starts_with("admin"))
 }
 
 pub async fn find_moderator_roles(
     guild_id: Id<GuildMarker>,
     redis: &RedisClient,
 ) -> Result<Vec<CachedRoleProto>> {
-    Ok(redis
-        .guild(guild_id)
+    let mut guild = redis.guild(guild_id);
+    let config: RoleConfig = guild.configs().get().await?;
+    Ok(guild
         .fetch_all_resources::<Role>()
         .await?
         .into_values()
-        .filter(is_moderator_role)
+        .filter(|role| is_moderator_role(role, &config))
         .collect())
 }
 
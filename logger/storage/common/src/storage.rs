@@ -23,9 +23,10 @@ impl Storage {
     }
 
     pub async fn init(config: &HouraiConfig) -> Self {
+        let sql = hourai_sql::init(config).await;
         Self {
-            sql: hourai_sql::init(config).await,
-            redis: hourai_redis::init(config).await,
+            redis: hourai_redis::init(config, sql.clone()).await,
+            sql,
         }
     }
 
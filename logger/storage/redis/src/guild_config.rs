@@ -1,4 +1,5 @@
 use hourai::proto::{auto_config::*, guild_configs::*};
+use protobuf::{Message, ProtobufResult};
 
 pub trait CachedGuildConfig {
     const SUBKEY: u8;
@@ -19,3 +20,34 @@ guild_config!(VerificationConfig, 3_u8);
 guild_config!(MusicConfig, 4_u8);
 guild_config!(AnnouncementConfig, 5_u8);
 guild_config!(RoleConfig, 6_u8);
+
+/// Serializes all of the configs that are set in a `GuildConfig`, paired with their subkeys.
+pub fn config_sections(config: &GuildConfig) -> ProtobufResult<Vec<(u8, Vec<u8>)>> {
+    fn section<T: Message + CachedGuildConfig>(config: &T) -> ProtobufResult<(u8, Vec<u8>)> {
+        Ok((T::SUBKEY, config.write_to_bytes()?))
+    }
+
+    let mut sections = Vec::new();
+    if config.has_logging() {
+        sections.push(section(config.get_logging())?);
+    }
+    if config.has_verification() {
+        sections.push(section(config.get_verification())?);
+    }
+    if config.has_auto() {
+        sections.push(section(config.get_auto())?);
+    }
+    if config.has_moderation() {
+        sections.push(section(config.get_moderation())?);
+    }
+    if config.has_music() {
+        sections.push(section(config.get_music())?);
+    }
+    if config.has_announce() {
+        sections.push(section(config.get_announce())?);
+    }
+    if config.has_role() {
+        sections.push(section(config.get_role())?);
+    }
+    Ok(sections)
+}
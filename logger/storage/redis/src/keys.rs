@@ -25,6 +25,17 @@ pub enum CacheKey {
     ResumeState(/* Name */ String),
     /// The stored music queues for each server. Used to restore the music state after a restart.
     MusicQueue(TwilightId<GuildMarker>),
+    /// The use counts of each server's invites, keyed by invite code. Used to determine which
+    /// invite a new member joined with.
+    Invites(TwilightId<GuildMarker>),
+    /// Hashes of users whose streams have been announced, keyed by user ID. Used to debounce
+    /// stream announcements.
+    Streams(TwilightId<GuildMarker>),
+    /// Escalations awaiting confirmation, keyed by the ID of the interaction that created them.
+    PendingEscalation(TwilightId<InteractionMarker>),
+    /// The last username seen in a member update for each user. Used to log each username change
+    /// once, even though an update is received for every server the user is in.
+    Username(TwilightId<UserMarker>),
 }
 
 impl CacheKey {
@@ -37,6 +48,10 @@ impl CacheKey {
             Self::VoiceState(_) => 5_u8,
             Self::ResumeState(_) => 6_u8,
             Self::MusicQueue(_) => 7_u8,
+            Self::Invites(_) => 8_u8,
+            Self::Streams(_) => 9_u8,
+            Self::PendingEscalation(_) => 10_u8,
+            Self::Username(_) => 11_u8,
         }
     }
 }
@@ -58,6 +73,12 @@ impl ToRedisArgs for CacheKey {
                 PrefixedKey(self.prefix(), key.as_str()).write_redis_args(out)
             }
             Self::MusicQueue(id) => PrefixedKey(self.prefix(), id.get()).write_redis_args(out),
+            Self::Invites(id) => PrefixedKey(self.prefix(), id.get()).write_redis_args(out),
+            Self::Streams(id) => PrefixedKey(self.prefix(), id.get()).write_redis_args(out),
+            Self::PendingEscalation(id) => {
+                PrefixedKey(self.prefix(), id.get()).write_redis_args(out)
+            }
+            Self::Username(id) => PrefixedKey(self.prefix(), id.get()).write_redis_args(out),
         }
     }
 }
@@ -7,21 +7,23 @@ mod protobuf;
 pub use redis::*;
 
 use self::compression::Compressed;
-pub use self::guild_config::CachedGuildConfig;
+pub use self::guild_config::{config_sections, CachedGuildConfig};
 use self::keys::{CacheKey, GuildKey, Id};
 use self::protobuf::Protobuf;
 use anyhow::Result;
+use byteorder::{BigEndian, ByteOrder};
 use hourai::{
     gateway::shard::ResumeSession,
     models::{
-        channel::Channel,
+        channel::{permission_overwrite::PermissionOverwriteType, Channel},
         guild::{Guild, PartialGuild, Permissions, Role},
         id::{marker::*, Id as TwilightId},
         voice::VoiceState,
         MessageLike, Snowflake, UserLike,
     },
-    proto::{cache::*, music_bot::MusicStateProto},
+    proto::{cache::*, escalation::PendingEscalation, guild_configs, music_bot::MusicStateProto},
 };
+use hourai_sql::{GuildConfigRecord, SqlPool};
 use redis::{FromRedisValue, ToRedisArgs};
 use std::{
     borrow::Cow,
@@ -33,29 +35,56 @@ use tracing::debug;
 
 type RedisPool = redis::aio::ConnectionManager;
 
-pub async fn init(config: &hourai::config::HouraiConfig) -> RedisClient {
+/// The hash field in a guild's configs that marks that they have been loaded from the database.
+const GUILD_CONFIGS_LOADED: u8 = u8::MAX;
+
+pub async fn init(config: &hourai::config::HouraiConfig, sql: SqlPool) -> RedisClient {
     debug!("Creating Redis client");
     let client = redis::Client::open(config.redis.as_ref()).expect("Failed to create Redis client");
     let pool = RedisPool::new(client)
         .await
         .expect("Failed to initialize multiplexed Redis connection");
-    RedisClient::new(pool)
+    RedisClient::new(pool, sql)
 }
 
+/// A client for the Redis cache. Guild configs are durably stored in the database, which the
+/// client reads through to and writes through to as needed.
 #[derive(Clone)]
-pub struct RedisClient(RedisPool);
+pub struct RedisClient {
+    connection: RedisPool,
+    sql: SqlPool,
+}
 
 impl RedisClient {
-    pub fn new(connection: RedisPool) -> Self {
-        Self(connection)
+    pub fn new(connection: RedisPool, sql: SqlPool) -> Self {
+        Self { connection, sql }
     }
 
     pub fn connection(&self) -> &RedisPool {
-        &self.0
+        &self.connection
     }
 
     pub fn connection_mut(&mut self) -> &mut RedisPool {
-        &mut self.0
+        &mut self.connection
+    }
+
+    /// Lists all of the guilds with configs stored in the cache.
+    pub async fn guilds_with_configs(&mut self) -> Result<Vec<TwilightId<GuildMarker>>> {
+        // Guild config keys are a single byte prefix followed by the big endian guild ID.
+        let prefix = CacheKey::GuildConfigs(TwilightId::new(1)).prefix();
+        let mut keys: Vec<Vec<u8>> = Vec::new();
+        let mut iter = self
+            .connection
+            .scan_match::<_, Vec<u8>>(&[prefix, b'*'][..])
+            .await?;
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        Ok(keys
+            .into_iter()
+            .filter(|key| key.len() == 9 && key[0] == prefix)
+            .filter_map(|key| TwilightId::new_checked(BigEndian::read_u64(&key[1..9])))
+            .collect())
     }
 
     pub fn online_status(&self) -> OnlineStatus {
@@ -76,6 +105,14 @@ impl RedisClient {
     pub fn resume_states(&self) -> ResumeStates {
         ResumeStates(self.clone())
     }
+
+    pub fn pending_escalations(&self) -> PendingEscalations {
+        PendingEscalations(self.clone())
+    }
+
+    pub fn usernames(&self) -> Usernames {
+        Usernames(self.clone())
+    }
 }
 
 pub struct OnlineStatus(RedisClient);
@@ -124,25 +161,45 @@ impl OnlineStatus {
 pub struct GuildConfig(GuildCache);
 
 impl GuildConfig {
+    /// Fetches one of the guild's configs. If the guild's configs are not in the cache, they are
+    /// loaded from the database.
     pub async fn fetch<T: ::protobuf::Message + CachedGuildConfig>(&mut self) -> Result<Option<T>> {
         let key = CacheKey::GuildConfigs(self.0.guild_id);
-        let response: Option<Compressed<Protobuf<T>>> = self
+        let (config, loaded): (Option<Compressed<Protobuf<T>>>, Option<bool>) = self
             .0
             .redis
             .connection_mut()
-            .hget(key, vec![T::SUBKEY])
+            .hget(key, vec![vec![T::SUBKEY], vec![GUILD_CONFIGS_LOADED]])
             .await?;
-        Ok(response.map(|c| c.0 .0))
+        if let Some(config) = config {
+            return Ok(Some(config.0 .0));
+        } else if loaded.is_some() {
+            return Ok(None);
+        }
+
+        let record = self
+            .load()
+            .await?
+            .into_iter()
+            .find(|record| record.subkey == T::SUBKEY as i16);
+        match record {
+            Some(record) => Ok(Some(T::parse_from_bytes(&record.config)?)),
+            None => Ok(None),
+        }
     }
 
     pub async fn get<T: ::protobuf::Message + CachedGuildConfig>(&mut self) -> Result<T> {
         Ok(self.fetch::<T>().await?.unwrap_or_else(T::new))
     }
 
+    /// Sets one of the guild's configs in both the database and the cache.
     pub async fn set<T: ::protobuf::Message + CachedGuildConfig>(
         &mut self,
         value: T,
     ) -> Result<()> {
+        GuildConfigRecord::upsert(self.0.guild_id, T::SUBKEY, value.write_to_bytes()?)
+            .execute(&self.0.redis.sql)
+            .await?;
         let key = CacheKey::GuildConfigs(self.0.guild_id);
         self.0
             .redis
@@ -151,6 +208,95 @@ impl GuildConfig {
             .await?;
         Ok(())
     }
+
+    /// Fetches all of the configs for the guild. Configs that have not been set are left unset in
+    /// the returned proto.
+    pub async fn fetch_all(&mut self) -> Result<guild_configs::GuildConfig> {
+        let mut config = guild_configs::GuildConfig::new();
+        if let Some(logging) = self.fetch().await? {
+            config.set_logging(logging);
+        }
+        if let Some(verification) = self.fetch().await? {
+            config.set_verification(verification);
+        }
+        if let Some(auto) = self.fetch().await? {
+            config.set_auto(auto);
+        }
+        if let Some(moderation) = self.fetch().await? {
+            config.set_moderation(moderation);
+        }
+        if let Some(music) = self.fetch().await? {
+            config.set_music(music);
+        }
+        if let Some(announce) = self.fetch().await? {
+            config.set_announce(announce);
+        }
+        if let Some(role) = self.fetch().await? {
+            config.set_role(role);
+        }
+        Ok(config)
+    }
+
+    /// Atomically overwrites all of the configs that are set in the provided proto. Configs that
+    /// are not set are left untouched.
+    pub async fn set_all(&mut self, config: guild_configs::GuildConfig) -> Result<()> {
+        let key = CacheKey::GuildConfigs(self.0.guild_id);
+        let sections = config_sections(&config)?;
+        let mut txn = self.0.redis.sql.begin().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for (subkey, data) in sections {
+            GuildConfigRecord::upsert(self.0.guild_id, subkey, data.clone())
+                .execute(&mut txn)
+                .await?;
+            pipe.hset(key.clone(), vec![subkey], Compressed(data))
+                .ignore();
+        }
+        pipe.query_async(self.0.redis.connection_mut()).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Copies all of the guild's cached configs into the database. Configs that are already
+    /// stored in the database are not overwritten. Returns the number of configs copied.
+    pub async fn persist(&mut self) -> Result<u64> {
+        let key = CacheKey::GuildConfigs(self.0.guild_id);
+        let configs: HashMap<Vec<u8>, Compressed<Vec<u8>>> =
+            self.0.redis.connection_mut().hgetall(key).await?;
+        let mut count = 0;
+        for (subkey, config) in configs {
+            if subkey.len() != 1 || subkey[0] == GUILD_CONFIGS_LOADED {
+                continue;
+            }
+            count += GuildConfigRecord::insert_missing(self.0.guild_id, subkey[0], config.0)
+                .execute(&self.0.redis.sql)
+                .await?
+                .rows_affected();
+        }
+        Ok(count)
+    }
+
+    /// Loads all of the guild's configs from the database into the cache. Configs that are
+    /// already in the cache are not overwritten.
+    async fn load(&mut self) -> Result<Vec<GuildConfigRecord>> {
+        let key = CacheKey::GuildConfigs(self.0.guild_id);
+        let records = GuildConfigRecord::fetch_guild(self.0.guild_id)
+            .fetch_all(&self.0.redis.sql)
+            .await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for record in records.iter() {
+            pipe.hset_nx(
+                key.clone(),
+                vec![record.subkey as u8],
+                Compressed(record.config.clone()),
+            )
+            .ignore();
+        }
+        pipe.hset(key, vec![GUILD_CONFIGS_LOADED], true).ignore();
+        pipe.query_async(self.0.redis.connection_mut()).await?;
+        Ok(records)
+    }
 }
 
 pub struct MessageCache(RedisClient);
@@ -173,7 +319,46 @@ impl MessageCache {
             user.set_avatar(avatar.to_string());
         }
 
-        let key = CacheKey::Messages(message.channel_id(), message.id());
+        for attachment in message.attachments() {
+            let proto = msg.mut_attachments().push_default();
+            proto.set_id(attachment.id.get());
+            proto.set_filename(attachment.filename.clone());
+            proto.set_size(attachment.size);
+            proto.set_url(attachment.url.clone());
+            proto.set_proxy_url(attachment.proxy_url.clone());
+            if let Some(ref content_type) = attachment.content_type {
+                proto.set_content_type(content_type.clone());
+            }
+        }
+
+        for embed in message.embeds() {
+            let proto = msg.mut_embeds().push_default();
+            proto.set_kind(embed.kind.clone());
+            if let Some(ref title) = embed.title {
+                proto.set_title(title.clone());
+            }
+            if let Some(ref description) = embed.description {
+                proto.set_description(description.clone());
+            }
+            if let Some(ref url) = embed.url {
+                proto.set_url(url.clone());
+            }
+            if let Some(ref image) = embed.image {
+                proto.set_image_url(image.url.clone());
+            }
+        }
+
+        self.save(message.channel_id(), message.id(), msg).await
+    }
+
+    /// Saves an already cached message back into the cache.
+    pub async fn save(
+        &mut self,
+        channel_id: TwilightId<ChannelMarker>,
+        message_id: TwilightId<MessageMarker>,
+        msg: CachedMessageProto,
+    ) -> Result<()> {
+        let key = CacheKey::Messages(channel_id, message_id);
         // Keep 1 day's worth of messages cached.
         self.0
             .connection_mut()
@@ -198,6 +383,32 @@ impl MessageCache {
         }))
     }
 
+    /// Fetches all of the provided messages that are still in the cache. Messages that are not
+    /// cached are omitted from the results.
+    pub async fn fetch_all(
+        &mut self,
+        channel_id: TwilightId<ChannelMarker>,
+        ids: impl IntoIterator<Item = TwilightId<MessageMarker>>,
+    ) -> Result<Vec<CachedMessageProto>> {
+        let ids: Vec<TwilightId<MessageMarker>> = ids.into_iter().collect();
+        let mut pipe = redis::pipe();
+        for id in ids.iter() {
+            pipe.get(CacheKey::Messages(channel_id, *id));
+        }
+        let protos: Vec<Option<Protobuf<CachedMessageProto>>> =
+            pipe.query_async(self.0.connection_mut()).await?;
+        Ok(ids
+            .into_iter()
+            .zip(protos)
+            .filter_map(|(id, proto)| {
+                let mut proto = proto?.0;
+                proto.set_id(id.get());
+                proto.set_channel_id(channel_id.get());
+                Some(proto)
+            })
+            .collect())
+    }
+
     pub async fn delete(
         &mut self,
         channel_id: TwilightId<ChannelMarker>,
@@ -223,10 +434,32 @@ impl MessageCache {
 pub struct VoiceStateCache(GuildCache);
 
 impl VoiceStateCache {
+    /// Replaces the cached voice states with the ones in the guild. Announced streams are kept for
+    /// users that are still streaming so that reconnecting does not announce them again.
     pub async fn update_guild(&mut self, guild: &Guild) -> Result<()> {
         assert!(self.0.guild_id == guild.id);
+        let announced: Vec<u64> = self
+            .0
+            .redis
+            .connection_mut()
+            .hkeys(CacheKey::Streams(guild.id))
+            .await?;
+        let streaming: HashSet<u64> = guild
+            .voice_states
+            .iter()
+            .filter(|state| state.self_stream && state.channel_id.is_some())
+            .map(|state| state.user_id.get())
+            .collect();
+        let ended: Vec<u64> = announced
+            .into_iter()
+            .filter(|user_id| !streaming.contains(user_id))
+            .collect();
+
         let mut pipe = redis::pipe();
         pipe.atomic().del(CacheKey::VoiceState(guild.id)).ignore();
+        if !ended.is_empty() {
+            pipe.hdel(CacheKey::Streams(guild.id), ended).ignore();
+        }
         for state in guild.voice_states.iter() {
             pipe.add_command(self.save_cmd(state)).ignore();
         }
@@ -270,10 +503,50 @@ impl VoiceStateCache {
     }
 
     pub async fn clear(&mut self) -> Result<()> {
+        let keys = vec![
+            CacheKey::VoiceState(self.0.guild_id),
+            CacheKey::Streams(self.0.guild_id),
+        ];
+        self.0.redis.connection_mut().del(keys).await?;
+        Ok(())
+    }
+
+    /// Gets the state of a user's announced stream, if it has been announced. See `set_stream`.
+    pub async fn get_stream(&mut self, user_id: TwilightId<UserMarker>) -> Result<Option<u64>> {
+        let stopped_at: Option<u64> = self
+            .0
+            .redis
+            .connection_mut()
+            .hget(CacheKey::Streams(self.0.guild_id), user_id.get())
+            .await?;
+        Ok(stopped_at)
+    }
+
+    /// Marks a user's stream as announced. `stopped_at` is zero while the user is still streaming,
+    /// and otherwise the time in milliseconds that the stream stopped, which identifies the
+    /// pending announcement of the stop.
+    pub async fn set_stream(
+        &mut self,
+        user_id: TwilightId<UserMarker>,
+        stopped_at: u64,
+    ) -> Result<()> {
+        self.0
+            .redis
+            .connection_mut()
+            .hset(
+                CacheKey::Streams(self.0.guild_id),
+                user_id.get(),
+                stopped_at,
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn clear_stream(&mut self, user_id: TwilightId<UserMarker>) -> Result<()> {
         self.0
             .redis
             .connection_mut()
-            .del(CacheKey::VoiceState(self.0.guild_id))
+            .hdel(CacheKey::Streams(self.0.guild_id), user_id.get())
             .await?;
         Ok(())
     }
@@ -288,6 +561,34 @@ impl VoiceStateCache {
     }
 }
 
+pub struct InviteCache(GuildCache);
+
+impl InviteCache {
+    /// Fetches the last saved use counts of the server's invites, keyed by invite code.
+    pub async fn fetch(&mut self) -> Result<HashMap<String, u64>> {
+        let uses: HashMap<String, u64> = self
+            .0
+            .redis
+            .connection_mut()
+            .hgetall(CacheKey::Invites(self.0.guild_id))
+            .await?;
+        Ok(uses)
+    }
+
+    /// Replaces the saved use counts of the server's invites.
+    pub async fn save(&mut self, uses: impl IntoIterator<Item = (String, u64)>) -> Result<()> {
+        let key = CacheKey::Invites(self.0.guild_id);
+        let uses: Vec<(String, u64)> = uses.into_iter().collect();
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(key.clone()).ignore();
+        if !uses.is_empty() {
+            pipe.hset_multiple(key, &uses).ignore();
+        }
+        pipe.query_async(self.0.redis.connection_mut()).await?;
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 pub struct GuildCache {
     guild_id: TwilightId<GuildMarker>,
@@ -307,6 +608,10 @@ impl GuildCache {
         VoiceStateCache(self.clone())
     }
 
+    pub fn invites(&self) -> InviteCache {
+        InviteCache(self.clone())
+    }
+
     pub async fn save(&mut self, guild: &hourai::models::guild::Guild) -> Result<()> {
         assert!(self.guild_id == guild.id);
         let key = CacheKey::Guild(guild.id);
@@ -580,6 +885,20 @@ impl ToProto for Guild {
         if let Some(ref code) = self.vanity_url_code {
             proto.set_vanity_url_code(code.clone());
         }
+        if let Some(icon) = self.icon {
+            proto.set_icon(icon.to_string());
+        }
+        if let Some(channel_id) = self.afk_channel_id {
+            proto.set_afk_channel_id(channel_id.get());
+        }
+        if let Some(channel_id) = self.system_channel_id {
+            proto.set_system_channel_id(channel_id.get());
+        }
+        if let Some(channel_id) = self.rules_channel_id {
+            proto.set_rules_channel_id(channel_id.get());
+        }
+        proto.set_verification_level(format!("{:?}", self.verification_level));
+        proto.set_explicit_content_filter(format!("{:?}", self.explicit_content_filter));
         proto
     }
 }
@@ -611,6 +930,20 @@ impl ToProto for PartialGuild {
         if let Some(ref code) = self.vanity_url_code {
             proto.set_vanity_url_code(code.clone());
         }
+        if let Some(icon) = self.icon {
+            proto.set_icon(icon.to_string());
+        }
+        if let Some(channel_id) = self.afk_channel_id {
+            proto.set_afk_channel_id(channel_id.get());
+        }
+        if let Some(channel_id) = self.system_channel_id {
+            proto.set_system_channel_id(channel_id.get());
+        }
+        if let Some(channel_id) = self.rules_channel_id {
+            proto.set_rules_channel_id(channel_id.get());
+        }
+        proto.set_verification_level(format!("{:?}", self.verification_level));
+        proto.set_explicit_content_filter(format!("{:?}", self.explicit_content_filter));
         proto
     }
 }
@@ -636,6 +969,25 @@ impl ToProto for Channel {
         let mut proto = Self::Proto::new();
         proto.set_channel_id(self.id.get());
         proto.set_name(self.name.as_ref().unwrap().to_owned());
+        if let Some(ref topic) = self.topic {
+            proto.set_topic(topic.clone());
+        }
+        if let Some(parent_id) = self.parent_id {
+            proto.set_parent_id(parent_id.get());
+        }
+        if let Some(position) = self.position {
+            proto.set_position(position.into());
+        }
+        if let Some(nsfw) = self.nsfw {
+            proto.set_nsfw(nsfw);
+        }
+        for overwrite in self.permission_overwrites.iter().flatten() {
+            let proto = proto.mut_permission_overwrites().push_default();
+            proto.set_id(overwrite.id.get());
+            proto.set_member(overwrite.kind == PermissionOverwriteType::Member);
+            proto.set_allow(overwrite.allow.bits());
+            proto.set_deny(overwrite.deny.bits());
+        }
         proto
     }
 }
@@ -662,6 +1014,9 @@ impl ToProto for Role {
         proto.set_name(self.name.clone());
         proto.set_position(self.position);
         proto.set_permissions(self.permissions.bits());
+        proto.set_color(self.color);
+        proto.set_hoist(self.hoist);
+        proto.set_mentionable(self.mentionable);
         proto
     }
 }
@@ -702,6 +1057,67 @@ impl ResumeStates {
     }
 }
 
+pub struct PendingEscalations(RedisClient);
+
+impl PendingEscalations {
+    /// Saves an escalation until it is confirmed. Pending escalations expire after 15 minutes.
+    pub async fn save(
+        &mut self,
+        interaction_id: TwilightId<InteractionMarker>,
+        escalation: PendingEscalation,
+    ) -> Result<()> {
+        self.0
+            .connection_mut()
+            .set_ex(
+                CacheKey::PendingEscalation(interaction_id),
+                Protobuf(escalation),
+                900,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Removes a pending escalation and returns it. Only one caller will receive a given
+    /// escalation, so it is applied at most once.
+    pub async fn take(
+        &mut self,
+        interaction_id: TwilightId<InteractionMarker>,
+    ) -> Result<Option<PendingEscalation>> {
+        let key = CacheKey::PendingEscalation(interaction_id);
+        let (escalation,): (Option<Protobuf<PendingEscalation>>,) = redis::pipe()
+            .atomic()
+            .get(key.clone())
+            .del(key)
+            .ignore()
+            .query_async(self.0.connection_mut())
+            .await?;
+        Ok(escalation.map(|escalation| escalation.0))
+    }
+}
+
+pub struct Usernames(RedisClient);
+
+impl Usernames {
+    /// Records a user's current username and returns the previously recorded one, if any. The swap
+    /// is atomic, so of several concurrent updates with the same new username, only one sees the
+    /// old username. Usernames expire after a week without updates.
+    pub async fn swap(
+        &mut self,
+        user_id: TwilightId<UserMarker>,
+        username: &str,
+    ) -> Result<Option<String>> {
+        let key = CacheKey::Username(user_id);
+        let (previous,): (Option<String>,) = redis::pipe()
+            .atomic()
+            .getset(key.clone(), username)
+            .expire(key, 7 * 24 * 3600)
+            .ignore()
+            .query_async(self.0.connection_mut())
+            .await?;
+        Ok(previous)
+    }
+}
+
 pub struct MusicQueues(GuildCache);
 
 impl MusicQueues {
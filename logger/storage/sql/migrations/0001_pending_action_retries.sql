@@ -0,0 +1,18 @@
+-- Tracks retries of pending actions and dead-letters actions that repeatedly
+-- fail to execute.
+ALTER TABLE public.pending_actions
+    ADD COLUMN IF NOT EXISTS attempts integer NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS last_error text;
+
+CREATE TABLE IF NOT EXISTS public.failed_actions (
+    id serial PRIMARY KEY,
+    guild_id bigint NOT NULL,
+    user_id bigint NOT NULL,
+    "timestamp" timestamp with time zone NOT NULL,
+    attempts integer NOT NULL,
+    error text NOT NULL,
+    data bytea NOT NULL
+);
+ALTER TABLE public.failed_actions OWNER TO hourai;
+CREATE INDEX IF NOT EXISTS failed_actions_guild_id_idx ON public.failed_actions USING btree (guild_id);
+GRANT SELECT ON TABLE public.failed_actions TO grafana;
@@ -0,0 +1,9 @@
+-- Records which guild and user a pending action targets so that they can be
+-- listed and cancelled by moderators. Actions scheduled before this migration
+-- are left with a guild_id and user_id of 0 until the logger backfills them
+-- from their action data on startup.
+ALTER TABLE public.pending_actions
+    ADD COLUMN IF NOT EXISTS guild_id bigint NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS user_id bigint NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS pending_actions_guild_id_idx ON public.pending_actions USING btree (guild_id, user_id);
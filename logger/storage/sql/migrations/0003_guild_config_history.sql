@@ -0,0 +1,13 @@
+-- Append-only history of every guild config write, so that previous versions
+-- of a config can be inspected and restored.
+CREATE TABLE IF NOT EXISTS public.guild_config_history (
+    id serial PRIMARY KEY,
+    guild_id bigint NOT NULL,
+    subkey smallint NOT NULL,
+    author_id bigint NOT NULL,
+    "timestamp" timestamp with time zone NOT NULL,
+    config bytea NOT NULL
+);
+ALTER TABLE public.guild_config_history OWNER TO hourai;
+CREATE INDEX IF NOT EXISTS guild_config_history_guild_id_idx ON public.guild_config_history USING btree (guild_id, subkey);
+GRANT SELECT ON TABLE public.guild_config_history TO grafana;
@@ -0,0 +1,11 @@
+-- Durable storage for guild configs. Redis only acts as a cache of this table.
+-- Existing configs that only live in Redis can be copied into this table with
+-- the migrate_guild_configs binary.
+CREATE TABLE IF NOT EXISTS public.guild_configs (
+    guild_id bigint NOT NULL,
+    subkey smallint NOT NULL,
+    config bytea NOT NULL,
+    PRIMARY KEY (guild_id, subkey)
+);
+ALTER TABLE public.guild_configs OWNER TO hourai;
+GRANT SELECT ON TABLE public.guild_configs TO grafana;
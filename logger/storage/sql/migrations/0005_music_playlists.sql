@@ -0,0 +1,11 @@
+-- Named music playlists. Each playlist is owned by either a user or a guild:
+-- owner_id is a user ID if guild_owned is false, and a guild ID otherwise.
+CREATE TABLE IF NOT EXISTS public.music_playlists (
+    owner_id bigint NOT NULL,
+    guild_owned boolean NOT NULL,
+    name varchar(100) NOT NULL,
+    tracks bytea NOT NULL,
+    PRIMARY KEY (owner_id, guild_owned, name)
+);
+ALTER TABLE public.music_playlists OWNER TO hourai;
+GRANT SELECT ON TABLE public.music_playlists TO grafana;
@@ -0,0 +1,5 @@
+-- Tracks worker leases on claimed pending actions separately from when the
+-- actions are scheduled to run, so that listings keep showing the scheduled
+-- time while an action is being executed.
+ALTER TABLE public.pending_actions
+    ADD COLUMN IF NOT EXISTS lease_expiration timestamp with time zone;
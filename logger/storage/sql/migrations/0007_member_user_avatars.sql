@@ -0,0 +1,4 @@
+-- Stores each member's global avatar alongside their server avatar so that
+-- member updates can detect when a user changes their account avatar.
+ALTER TABLE public.members
+    ADD COLUMN IF NOT EXISTS user_avatar text;
@@ -8,7 +8,10 @@ use hourai::{
         util::{image_hash::ImageHash, Timestamp},
         UserLike,
     },
-    proto::action::{Action, ActionSet},
+    proto::{
+        action::{Action, ActionSet},
+        music_bot::PlaylistProto,
+    },
 };
 use sqlx::types::chrono::{DateTime, NaiveDateTime, Utc};
 use std::convert::TryInto;
@@ -71,12 +74,24 @@ impl Username {
         }
     }
 
+    /// Fetches the most recently recorded username for a user.
+    pub fn fetch_latest<'a>(user_id: Id<UserMarker>) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "SELECT user_id, timestamp, name, discriminator \
+             FROM usernames WHERE user_id = $1 \
+             ORDER BY timestamp DESC LIMIT 1",
+        )
+        .bind(user_id.get() as i64)
+    }
+
+    /// Records a new username for a user. If the user previously had the same username, the old
+    /// record is moved forward to now so that it becomes the latest username again.
     pub fn insert(&self) -> SqlQuery {
         sqlx::query(
             "INSERT INTO usernames (user_id, name, discriminator) \
              VALUES ($1, $2, $3) \
              ON CONFLICT ON CONSTRAINT idx_unique_username \
-             DO NOTHING",
+             DO UPDATE SET timestamp = now()",
         )
         .bind(self.user_id)
         .bind(self.name.clone())
@@ -257,6 +272,7 @@ pub struct Member {
     pub present: bool,
     pub premium_since: Option<DateTime<Utc>>,
     pub avatar: Option<String>,
+    pub user_avatar: Option<String>,
 }
 
 impl From<&TwilightMember> for Member {
@@ -271,6 +287,7 @@ impl From<&TwilightMember> for Member {
             present: true,
             premium_since: premium,
             avatar: member.avatar.map(|hash| hash.to_string()),
+            user_avatar: member.user.avatar.map(|hash| hash.to_string()),
         }
     }
 }
@@ -287,6 +304,7 @@ impl From<&MemberUpdate> for Member {
             present: true,
             premium_since: premium,
             avatar: member.avatar.map(|hash| hash.to_string()),
+            user_avatar: member.user.avatar.map(|hash| hash.to_string()),
         }
     }
 }
@@ -330,15 +348,17 @@ impl Member {
                 present,
                 bot,
                 premium_since,
-                avatar
+                avatar,
+                user_avatar
             ) \
-            VALUES ($1, $2, $3, $4, true, $5, $6, $7) \
+            VALUES ($1, $2, $3, $4, true, $5, $6, $7, $8) \
             ON CONFLICT ON CONSTRAINT members_pkey \
             DO UPDATE SET \
                 role_ids = excluded.role_ids, \
                 nickname = excluded.nickname, \
                 premium_since = excluded.premium_since, \
                 avatar = excluded.avatar, \
+                user_avatar = excluded.user_avatar, \
                 bot = excluded.bot, \
                 last_seen = now(), \
                 present = true",
@@ -350,6 +370,7 @@ impl Member {
         .bind(self.bot)
         .bind(self.premium_since)
         .bind(self.avatar)
+        .bind(self.user_avatar)
     }
 
     pub fn has_nitro<'a>(user_id: Id<UserMarker>) -> SqlQueryAs<'a, (bool,)> {
@@ -394,6 +415,12 @@ impl Member {
             .bind(user_id.get() as i64)
     }
 
+    /// Fetches the IDs of all of the servers a user is currently a member of.
+    pub fn fetch_guild_ids<'a>(user_id: Id<UserMarker>) -> SqlQueryAs<'a, (i64,)> {
+        sqlx::query_as("SELECT guild_id FROM members WHERE user_id = $1 AND present")
+            .bind(user_id.get() as i64)
+    }
+
     pub fn find_with_roles<'a>(
         guild_id: Id<GuildMarker>,
         role_ids: impl IntoIterator<Item = Id<RoleMarker>>,
@@ -547,22 +574,149 @@ impl PendingDeescalation {
 #[derive(Debug, sqlx::FromRow)]
 pub struct PendingAction {
     id: i32,
+    guild_id: i64,
+    user_id: i64,
+    timestamp: DateTime<Utc>,
+    attempts: i32,
     data: types::Protobuf<Action>,
 }
 
 impl PendingAction {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// The guild the action targets. `None` for actions scheduled before the owner was recorded
+    /// that have not been backfilled yet.
+    pub fn guild_id(&self) -> Option<Id<GuildMarker>> {
+        Id::new_checked(self.guild_id as u64)
+    }
+
+    /// The user the action targets, if any.
+    pub fn user_id(&self) -> Option<Id<UserMarker>> {
+        Id::new_checked(self.user_id as u64)
+    }
+
+    /// The time the action is scheduled to run.
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+
     pub fn action(&self) -> &Action {
         &self.data.0
     }
 
-    pub fn fetch_expired<'a>() -> SqlQueryAs<'a, Self> {
-        sqlx::query_as("SELECT id, data FROM pending_actions WHERE timestamp < now()")
+    /// The number of times the action has been claimed for execution, including the current
+    /// attempt.
+    pub fn attempts(&self) -> i32 {
+        self.attempts
+    }
+
+    /// Claims up to `limit` expired actions for execution. Claimed actions are not visible to
+    /// other workers until `lease_expiration`, after which they will be retried if they have not
+    /// been deleted, rescheduled, or dead-lettered.
+    ///
+    /// Rows locked by other concurrent claims are skipped.
+    pub fn claim_expired<'a>(
+        limit: i64,
+        lease_expiration: impl Into<DateTime<Utc>>,
+    ) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "UPDATE pending_actions \
+             SET lease_expiration = $2, attempts = attempts + 1 \
+             WHERE id IN ( \
+                 SELECT id FROM pending_actions \
+                 WHERE timestamp < now() \
+                 AND (lease_expiration IS NULL OR lease_expiration < now()) \
+                 ORDER BY timestamp \
+                 LIMIT $1 \
+                 FOR UPDATE SKIP LOCKED \
+             ) \
+             RETURNING id, guild_id, user_id, timestamp, attempts, data",
+        )
+        .bind(limit)
+        .bind(lease_expiration.into())
+    }
+
+    pub fn fetch_guild<'a>(guild_id: Id<GuildMarker>) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "SELECT id, guild_id, user_id, timestamp, attempts, data FROM pending_actions \
+             WHERE guild_id = $1 ORDER BY timestamp",
+        )
+        .bind(guild_id.get() as i64)
+    }
+
+    pub fn fetch_user<'a>(
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+    ) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "SELECT id, guild_id, user_id, timestamp, attempts, data FROM pending_actions \
+             WHERE guild_id = $1 AND user_id = $2 ORDER BY timestamp",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+    }
+
+    /// Fetches the actions that do not have a guild recorded, such as those scheduled before the
+    /// guild and user IDs were stored alongside the action.
+    pub fn fetch_unowned<'a>() -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "SELECT id, guild_id, user_id, timestamp, attempts, data FROM pending_actions \
+             WHERE guild_id = 0",
+        )
+    }
+
+    /// Records the guild and user the action targets, as read from the action itself.
+    pub fn set_owner<'a>(&self) -> SqlQuery<'a> {
+        sqlx::query("UPDATE pending_actions SET guild_id = $2, user_id = $3 WHERE id = $1")
+            .bind(self.id)
+            .bind(self.action().get_guild_id() as i64)
+            .bind(self.action().get_user_id() as i64)
     }
 
     pub fn schedule<'a>(action: Action, timestamp: impl Into<DateTime<Utc>>) -> SqlQuery<'a> {
-        sqlx::query("INSERT INTO pending_actions (timestamp, data) VALUES ($1, $2)")
-            .bind(timestamp.into())
-            .bind(types::Protobuf(action))
+        sqlx::query(
+            "INSERT INTO pending_actions (guild_id, user_id, timestamp, data) \
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(action.get_guild_id() as i64)
+        .bind(action.get_user_id() as i64)
+        .bind(timestamp.into())
+        .bind(types::Protobuf(action))
+    }
+
+    /// Cancels a pending action. Only deletes the action if it belongs to the provided guild.
+    pub fn cancel<'a>(guild_id: Id<GuildMarker>, id: i32) -> SqlQuery<'a> {
+        sqlx::query("DELETE FROM pending_actions WHERE id = $1 AND guild_id = $2")
+            .bind(id)
+            .bind(guild_id.get() as i64)
+    }
+
+    /// Reschedules a failed action to be retried at a later time.
+    pub fn retry<'a>(&self, timestamp: impl Into<DateTime<Utc>>, error: String) -> SqlQuery<'a> {
+        sqlx::query(
+            "UPDATE pending_actions \
+             SET timestamp = $2, last_error = $3, lease_expiration = NULL \
+             WHERE id = $1",
+        )
+        .bind(self.id)
+        .bind(timestamp.into())
+        .bind(error)
+    }
+
+    /// Atomically moves the action into the failed_actions table.
+    pub fn dead_letter<'a>(&self, error: String) -> SqlQuery<'a> {
+        sqlx::query(
+            "WITH failed AS ( \
+                 DELETE FROM pending_actions WHERE id = $1 \
+                 RETURNING guild_id, user_id, attempts, data \
+             ) \
+             INSERT INTO failed_actions (guild_id, user_id, timestamp, attempts, error, data) \
+             SELECT guild_id, user_id, now(), attempts, $2, data FROM failed",
+        )
+        .bind(self.id)
+        .bind(error)
     }
 
     pub fn delete<'a>(&self) -> SqlQuery<'a> {
@@ -570,6 +724,295 @@ impl PendingAction {
     }
 }
 
+#[derive(Debug, sqlx::FromRow)]
+pub struct FailedAction {
+    pub id: i32,
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub attempts: i32,
+    pub error: String,
+    pub data: types::Protobuf<Action>,
+}
+
+impl FailedAction {
+    pub fn action(&self) -> &Action {
+        &self.data.0
+    }
+
+    pub fn fetch_guild<'a>(guild_id: Id<GuildMarker>, limit: i64) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "SELECT * FROM failed_actions WHERE guild_id = $1 ORDER BY timestamp DESC LIMIT $2",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(limit)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct GuildConfigRecord {
+    pub guild_id: i64,
+    pub subkey: i16,
+    pub config: Vec<u8>,
+}
+
+impl GuildConfigRecord {
+    pub fn fetch_guild<'a>(guild_id: Id<GuildMarker>) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as("SELECT * FROM guild_configs WHERE guild_id = $1")
+            .bind(guild_id.get() as i64)
+    }
+
+    /// Inserts or overwrites one of a guild's configs.
+    pub fn upsert<'a>(guild_id: Id<GuildMarker>, subkey: u8, config: Vec<u8>) -> SqlQuery<'a> {
+        sqlx::query(
+            "INSERT INTO guild_configs (guild_id, subkey, config) VALUES ($1, $2, $3) \
+             ON CONFLICT (guild_id, subkey) DO UPDATE SET config = EXCLUDED.config",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(subkey as i16)
+        .bind(config)
+    }
+
+    /// Inserts one of a guild's configs only if it has not already been stored.
+    pub fn insert_missing<'a>(
+        guild_id: Id<GuildMarker>,
+        subkey: u8,
+        config: Vec<u8>,
+    ) -> SqlQuery<'a> {
+        sqlx::query(
+            "INSERT INTO guild_configs (guild_id, subkey, config) VALUES ($1, $2, $3) \
+             ON CONFLICT (guild_id, subkey) DO NOTHING",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(subkey as i16)
+        .bind(config)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct GuildConfigRevision {
+    pub id: i32,
+    pub guild_id: i64,
+    pub subkey: i16,
+    pub author_id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub config: Vec<u8>,
+}
+
+impl GuildConfigRevision {
+    /// Fetches the most recent revisions of one of a guild's configs, newest first.
+    pub fn fetch_history<'a>(
+        guild_id: Id<GuildMarker>,
+        subkey: u8,
+        limit: i64,
+    ) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "SELECT * FROM guild_config_history WHERE guild_id = $1 AND subkey = $2 \
+             ORDER BY id DESC LIMIT $3",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(subkey as i16)
+        .bind(limit)
+    }
+
+    pub fn fetch<'a>(guild_id: Id<GuildMarker>, subkey: u8, id: i32) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "SELECT * FROM guild_config_history WHERE guild_id = $1 AND subkey = $2 AND id = $3",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(subkey as i16)
+        .bind(id)
+    }
+
+    /// Records a new revision of a guild's config. Returns the ID of the new revision.
+    pub fn insert<'a>(
+        guild_id: Id<GuildMarker>,
+        subkey: u8,
+        author_id: Id<UserMarker>,
+        config: Vec<u8>,
+    ) -> SqlQueryAs<'a, (i32,)> {
+        sqlx::query_as(
+            "INSERT INTO guild_config_history (guild_id, subkey, author_id, timestamp, config) \
+             VALUES ($1, $2, $3, now(), $4) RETURNING id",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(subkey as i16)
+        .bind(author_id.get() as i64)
+        .bind(config)
+    }
+}
+
+/// The owner of a music playlist.
+#[derive(Clone, Copy, Debug)]
+pub enum PlaylistOwner {
+    User(Id<UserMarker>),
+    Guild(Id<GuildMarker>),
+}
+
+impl PlaylistOwner {
+    fn id(&self) -> i64 {
+        match self {
+            Self::User(id) => id.get() as i64,
+            Self::Guild(id) => id.get() as i64,
+        }
+    }
+
+    fn is_guild(&self) -> bool {
+        matches!(self, Self::Guild(_))
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Playlist {
+    pub name: String,
+    tracks: types::Protobuf<PlaylistProto>,
+}
+
+impl Playlist {
+    pub fn tracks(&self) -> &PlaylistProto {
+        &self.tracks.0
+    }
+
+    pub fn fetch<'a>(owner: PlaylistOwner, name: &str) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "SELECT name, tracks FROM music_playlists \
+             WHERE owner_id = $1 AND guild_owned = $2 AND name = $3",
+        )
+        .bind(owner.id())
+        .bind(owner.is_guild())
+        .bind(name.to_owned())
+    }
+
+    /// Fetches all of the playlists of an owner, ordered by name.
+    pub fn fetch_all<'a>(owner: PlaylistOwner) -> SqlQueryAs<'a, Self> {
+        sqlx::query_as(
+            "SELECT name, tracks FROM music_playlists \
+             WHERE owner_id = $1 AND guild_owned = $2 ORDER BY name",
+        )
+        .bind(owner.id())
+        .bind(owner.is_guild())
+    }
+
+    /// Counts the playlists of an owner.
+    pub fn count<'a>(owner: PlaylistOwner) -> SqlQueryAs<'a, (i64,)> {
+        sqlx::query_as(
+            "SELECT COUNT(*) FROM music_playlists WHERE owner_id = $1 AND guild_owned = $2",
+        )
+        .bind(owner.id())
+        .bind(owner.is_guild())
+    }
+
+    /// Saves a playlist, overwriting any existing playlist with the same name.
+    pub fn save<'a>(owner: PlaylistOwner, name: &str, tracks: PlaylistProto) -> SqlQuery<'a> {
+        sqlx::query(
+            "INSERT INTO music_playlists (owner_id, guild_owned, name, tracks) \
+             VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (owner_id, guild_owned, name) DO UPDATE SET tracks = EXCLUDED.tracks",
+        )
+        .bind(owner.id())
+        .bind(owner.is_guild())
+        .bind(name.to_owned())
+        .bind(types::Protobuf(tracks))
+    }
+
+    pub fn delete<'a>(owner: PlaylistOwner, name: &str) -> SqlQuery<'a> {
+        sqlx::query(
+            "DELETE FROM music_playlists WHERE owner_id = $1 AND guild_owned = $2 AND name = $3",
+        )
+        .bind(owner.id())
+        .bind(owner.is_guild())
+        .bind(name.to_owned())
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct FeedSubscription {
+    pub feed_id: i32,
+    pub feed_type: String,
+    pub source: String,
+    pub channel_id: i64,
+}
+
+impl FeedSubscription {
+    /// Fetches all of the feed subscriptions for a set of channels, ordered by channel.
+    pub fn fetch_channels<'a>(
+        channel_ids: impl IntoIterator<Item = Id<ChannelMarker>>,
+    ) -> SqlQueryAs<'a, Self> {
+        let channel_ids: Vec<i64> = channel_ids.into_iter().map(|id| id.get() as i64).collect();
+        sqlx::query_as(
+            "SELECT \
+                 feeds.id AS feed_id, feeds.type AS feed_type, feeds.source, \
+                 feed_channels.channel_id \
+             FROM \
+                 feeds \
+             INNER JOIN \
+                 feed_channels ON feeds.id = feed_channels.feed_id \
+             WHERE \
+                 feed_channels.channel_id = ANY($1) \
+             ORDER BY \
+                 feed_channels.channel_id, feeds.type, feeds.source",
+        )
+        .bind(channel_ids)
+    }
+
+    /// Creates a feed if it does not already exist. Returns the ID of the feed.
+    pub fn create_feed<'a>(
+        feed_type: impl Into<String>,
+        source: impl Into<String>,
+    ) -> SqlQueryAs<'a, (i32,)> {
+        sqlx::query_as(
+            "INSERT INTO feeds (type, source, last_updated) VALUES ($1, $2, now()) \
+             ON CONFLICT (type, source) DO UPDATE SET type = EXCLUDED.type \
+             RETURNING id",
+        )
+        .bind(feed_type.into())
+        .bind(source.into())
+    }
+
+    pub fn subscribe<'a>(feed_id: i32, channel_id: Id<ChannelMarker>) -> SqlQuery<'a> {
+        sqlx::query(
+            "INSERT INTO feed_channels (feed_id, channel_id) \
+             SELECT $1, $2 WHERE NOT EXISTS ( \
+                 SELECT 1 FROM feed_channels WHERE feed_id = $1 AND channel_id = $2 \
+             )",
+        )
+        .bind(feed_id)
+        .bind(channel_id.get() as i64)
+    }
+
+    pub fn unsubscribe<'a>(
+        feed_type: impl Into<String>,
+        source: impl Into<String>,
+        channel_id: Id<ChannelMarker>,
+    ) -> SqlQuery<'a> {
+        sqlx::query(
+            "DELETE FROM feed_channels USING feeds \
+             WHERE feed_channels.feed_id = feeds.id \
+                 AND feeds.type = $1 \
+                 AND feeds.source = $2 \
+                 AND feed_channels.channel_id = $3",
+        )
+        .bind(feed_type.into())
+        .bind(source.into())
+        .bind(channel_id.get() as i64)
+    }
+
+    /// Deletes a feed if it is no longer posted to any channel.
+    pub fn delete_unused_feed<'a>(
+        feed_type: impl Into<String>,
+        source: impl Into<String>,
+    ) -> SqlQuery<'a> {
+        sqlx::query(
+            "DELETE FROM feeds \
+             WHERE type = $1 AND source = $2 AND NOT EXISTS ( \
+                 SELECT 1 FROM feed_channels WHERE feed_channels.feed_id = feeds.id \
+             )",
+        )
+        .bind(feed_type.into())
+        .bind(source.into())
+    }
+}
+
 #[derive(Debug, sqlx::FromRow)]
 pub struct Oauth {
     pub user_id: i64,